@@ -0,0 +1,246 @@
+use crate::helpers::{alt_pressed, shape_bounds, shift_pressed, transformed_aabb, BoundsQuery};
+use crate::history::{EditAction, HistoryEvent};
+use crate::layers::Locked;
+use crate::{MouseMovement, ShapeBase};
+use bevy::prelude::*;
+use bevy_egui::EguiContext;
+use bevy_mod_picking::Selection;
+
+pub struct AlignPlugin;
+
+impl Plugin for AlignPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<AlignEvent>()
+            .add_system(handle_align_input)
+            .add_system(apply_alignment.after(handle_align_input).after("egui"));
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Alignment {
+    Left,
+    Center,
+    Right,
+    Top,
+    Middle,
+    Bottom,
+    CentersHorizontally,
+    CentersVertically,
+    GapsHorizontally,
+    GapsVertically,
+}
+
+impl Alignment {
+    pub const ALIGN: [(Alignment, &'static str); 6] = [
+        (Alignment::Left, "Left"),
+        (Alignment::Center, "Center"),
+        (Alignment::Right, "Right"),
+        (Alignment::Top, "Top"),
+        (Alignment::Middle, "Middle"),
+        (Alignment::Bottom, "Bottom"),
+    ];
+    pub const DISTRIBUTE: [(Alignment, &'static str); 4] = [
+        (Alignment::CentersHorizontally, "Centers horizontally"),
+        (Alignment::CentersVertically, "Centers vertically"),
+        (Alignment::GapsHorizontally, "Gaps horizontally"),
+        (Alignment::GapsVertically, "Gaps vertically"),
+    ];
+}
+
+pub struct AlignEvent(pub Alignment);
+
+/// Alt + L/C/R/T/M/B aligns, Alt + H/V distributes centers and Alt + Shift + H/V gaps.
+fn handle_align_input(
+    input: Res<Input<KeyCode>>,
+    mouse: Res<MouseMovement>,
+    mut events: EventWriter<AlignEvent>,
+    mut egui_context: ResMut<EguiContext>,
+) {
+    if !alt_pressed(&input) || mouse.over_ui || egui_context.ctx_mut().wants_keyboard_input() {
+        return;
+    }
+    let shift = shift_pressed(&input);
+    let keys = [
+        (KeyCode::L, Alignment::Left),
+        (KeyCode::C, Alignment::Center),
+        (KeyCode::R, Alignment::Right),
+        (KeyCode::T, Alignment::Top),
+        (KeyCode::M, Alignment::Middle),
+        (KeyCode::B, Alignment::Bottom),
+        (
+            KeyCode::H,
+            if shift {
+                Alignment::GapsHorizontally
+            } else {
+                Alignment::CentersHorizontally
+            },
+        ),
+        (
+            KeyCode::V,
+            if shift {
+                Alignment::GapsVertically
+            } else {
+                Alignment::CentersVertically
+            },
+        ),
+    ];
+    for (key, alignment) in keys {
+        if input.just_pressed(key) {
+            events.send(AlignEvent(alignment));
+        }
+    }
+}
+
+/// Evenly spaces the values of `rects` on one axis, keeping the outermost ones in place.
+/// `min` and `max` extract the extent of a rectangle on that axis.
+fn distribute(
+    rects: &[Rect],
+    gaps: bool,
+    min: impl Fn(&Rect) -> f32,
+    max: impl Fn(&Rect) -> f32,
+) -> Vec<f32> {
+    let center = |r: &Rect| (min(r) + max(r)) / 2.0;
+    let mut order = (0..rects.len()).collect::<Vec<_>>();
+    order.sort_by(|a, b| center(&rects[*a]).total_cmp(&center(&rects[*b])));
+    let mut offsets = vec![0.0; rects.len()];
+    let (first, last) = match (order.first(), order.last()) {
+        (Some(first), Some(last)) if rects.len() > 2 => (&rects[*first], &rects[*last]),
+        _ => return offsets,
+    };
+    let steps = (rects.len() - 1) as f32;
+    if gaps {
+        let sizes = rects.iter().map(|r| max(r) - min(r)).sum::<f32>();
+        let gap = (max(last) - min(first) - sizes) / steps;
+        let mut position = min(first);
+        for i in order {
+            offsets[i] = position - min(&rects[i]);
+            position += max(&rects[i]) - min(&rects[i]) + gap;
+        }
+    } else {
+        let step = (center(last) - center(first)) / steps;
+        for (n, i) in order.into_iter().enumerate() {
+            offsets[i] = center(first) + step * n as f32 - center(&rects[i]);
+        }
+    }
+    offsets
+}
+
+/// Translation of every rectangle that performs the given alignment.
+fn arrange(alignment: Alignment, rects: &[Rect]) -> Vec<Vec2> {
+    let bounds = match rects.iter().copied().reduce(|a, b| a.union(b)) {
+        Some(bounds) if rects.len() > 1 => bounds,
+        _ => return vec![Vec2::ZERO; rects.len()],
+    };
+    let x = |f: &dyn Fn(&Rect) -> f32| -> Vec<Vec2> {
+        rects.iter().map(|r| Vec2::new(f(r), 0.0)).collect()
+    };
+    let y = |f: &dyn Fn(&Rect) -> f32| -> Vec<Vec2> {
+        rects.iter().map(|r| Vec2::new(0.0, f(r))).collect()
+    };
+    match alignment {
+        Alignment::Left => x(&|r| bounds.min.x - r.min.x),
+        Alignment::Center => x(&|r| bounds.center().x - r.center().x),
+        Alignment::Right => x(&|r| bounds.max.x - r.max.x),
+        Alignment::Top => y(&|r| bounds.max.y - r.max.y),
+        Alignment::Middle => y(&|r| bounds.center().y - r.center().y),
+        Alignment::Bottom => y(&|r| bounds.min.y - r.min.y),
+        Alignment::CentersHorizontally | Alignment::GapsHorizontally => distribute(
+            rects,
+            alignment == Alignment::GapsHorizontally,
+            |r| r.min.x,
+            |r| r.max.x,
+        )
+        .into_iter()
+        .map(|d| Vec2::new(d, 0.0))
+        .collect(),
+        Alignment::CentersVertically | Alignment::GapsVertically => distribute(
+            rects,
+            alignment == Alignment::GapsVertically,
+            |r| r.min.y,
+            |r| r.max.y,
+        )
+        .into_iter()
+        .map(|d| Vec2::new(0.0, d))
+        .collect(),
+    }
+}
+
+/// Locked shapes neither move nor count towards the bounds the others are aligned to.
+fn apply_alignment(
+    mut events: EventReader<AlignEvent>,
+    selection: Query<(Entity, &Selection), (With<ShapeBase>, Without<Parent>, Without<Locked>)>,
+    meshes: Res<Assets<Mesh>>,
+    mut shapes: ParamSet<(BoundsQuery, Query<&mut Transform, With<ShapeBase>>)>,
+    mut history: EventWriter<HistoryEvent>,
+) {
+    for AlignEvent(alignment) in events.iter() {
+        let (entities, rects): (Vec<Entity>, Vec<Rect>) = selection
+            .iter()
+            .filter(|(_, s)| s.selected())
+            .filter_map(|(e, _)| {
+                let bounds = shapes.p0();
+                let aabb = shape_bounds(e, &meshes, &bounds)?;
+                Some((e, transformed_aabb(&aabb, bounds.get(e).ok()?.2)))
+            })
+            .unzip();
+        let offsets = arrange(*alignment, &rects);
+        let mut transforms = shapes.p1();
+        for (e, offset) in entities.into_iter().zip(offsets) {
+            if offset == Vec2::ZERO {
+                continue;
+            }
+            if let Ok(mut transform) = transforms.get_mut(e) {
+                let before = *transform;
+                transform.translation += offset.extend(0.0);
+                history.send(HistoryEvent(EditAction::Transformed {
+                    entity: e,
+                    before,
+                    after: *transform,
+                }));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn distribute_x(rects: &[Rect], gaps: bool) -> Vec<f32> {
+        distribute(rects, gaps, |r| r.min.x, |r| r.max.x)
+    }
+
+    fn spans(spans: &[(f32, f32)]) -> Vec<Rect> {
+        spans
+            .iter()
+            .map(|(min, max)| Rect::new(*min, 0.0, *max, 1.0))
+            .collect()
+    }
+
+    #[test]
+    fn fewer_than_three_shapes_stay() {
+        assert!(distribute_x(&[], false).is_empty());
+        let two = spans(&[(0.0, 10.0), (30.0, 35.0)]);
+        assert_eq!(distribute_x(&two, false), [0.0, 0.0]);
+        assert_eq!(distribute_x(&two, true), [0.0, 0.0]);
+    }
+
+    #[test]
+    fn centers_are_spaced_evenly() {
+        // Out of order, the outermost shapes are found by their centers.
+        let rects = spans(&[(40.0, 60.0), (0.0, 10.0), (11.0, 13.0)]);
+        assert_eq!(distribute_x(&rects, false), [0.0, 0.0, 15.5]);
+    }
+
+    #[test]
+    fn gaps_are_spaced_evenly() {
+        let rects = spans(&[(40.0, 60.0), (0.0, 10.0), (11.0, 13.0)]);
+        assert_eq!(distribute_x(&rects, true), [0.0, 0.0, 13.0]);
+    }
+
+    #[test]
+    fn single_shapes_are_not_aligned() {
+        let rects = spans(&[(5.0, 10.0)]);
+        assert_eq!(arrange(Alignment::Left, &rects), [Vec2::ZERO]);
+    }
+}
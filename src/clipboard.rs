@@ -0,0 +1,139 @@
+use crate::document::{spawn_shape, ShapeRecord, ShapeRecordQuery};
+use crate::helpers::{ctrl_pressed, shift_pressed};
+use crate::history::{EditAction, HistoryEvent};
+use crate::keyboard_input::delete_shapes;
+use crate::{ChangedOrderEvent, MouseMovement, OrderedShapes, ShapeBase};
+use bevy::prelude::*;
+use bevy_egui::EguiContext;
+use bevy_mod_picking::Selection;
+
+/// Distance between a shape and its copy, repeated pastes stack further away.
+const PASTE_OFFSET: Vec2 = Vec2::new(10.0, -10.0);
+
+pub struct ClipboardPlugin;
+
+impl Plugin for ClipboardPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ShapeClipboard>()
+            .add_system(handle_clipboard_input);
+    }
+}
+
+/// Copied top-level shapes, bottom to top, with the entity each one was copied from.
+#[derive(Resource, Default)]
+pub struct ShapeClipboard {
+    shapes: Vec<(Entity, ShapeRecord)>,
+    pastes: u32,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn copy_svg(records: &[ShapeRecord]) {
+    let svg = crate::svg_export::shapes_to_svg(records);
+    if let Err(e) = arboard::Clipboard::new().and_then(|mut c| c.set_text(svg)) {
+        warn!("failed to copy shapes to the system clipboard: {}", e);
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn copy_svg(_records: &[ShapeRecord]) {}
+
+/// Spawns copies of `shapes` moved by `offset`, each right above the shape it was copied from.
+fn paste_shapes(
+    commands: &mut Commands,
+    ordered: &mut OrderedShapes,
+    shapes: &[(Entity, ShapeRecord)],
+    offset: Vec2,
+    history: &mut EventWriter<HistoryEvent>,
+) -> Vec<Entity> {
+    let mut pasted = vec![];
+    for (source, record) in shapes {
+        let mut record = record.clone();
+        record.translation += offset.extend(0.0);
+        // A copy of a connector would be routed right back on top of the original.
+        record.detach_connectors();
+        let (e, _) = spawn_shape(commands, &record);
+        match ordered.0.iter().position(|o| o == source) {
+            Some(index) => ordered.0.insert(index + 1, e),
+            None => ordered.0.push(e),
+        }
+        history.send(HistoryEvent(EditAction::Created { entity: e }));
+        pasted.push(e);
+    }
+    pasted
+}
+
+#[allow(clippy::too_many_arguments)]
+fn handle_clipboard_input(
+    mut commands: Commands,
+    mut pending_selection: Local<Vec<Entity>>,
+    mut egui_context: ResMut<EguiContext>,
+    input: Res<Input<KeyCode>>,
+    mouse: Res<MouseMovement>,
+    mut clipboard: ResMut<ShapeClipboard>,
+    mut ordered: ResMut<OrderedShapes>,
+    mut selection: Query<&mut Selection, (With<ShapeBase>, Without<Parent>)>,
+    records: Query<ShapeRecordQuery>,
+    mut history: EventWriter<HistoryEvent>,
+    mut changed: EventWriter<ChangedOrderEvent>,
+) {
+    // Pasted shapes only exist once the commands of the previous frame were applied.
+    if !pending_selection.is_empty() {
+        for mut s in selection.iter_mut() {
+            s.set_selected(false);
+        }
+        for e in pending_selection.drain(..) {
+            if let Ok(mut s) = selection.get_mut(e) {
+                s.set_selected(true);
+            }
+        }
+    }
+    if !ctrl_pressed(&input) || mouse.over_ui || egui_context.ctx_mut().wants_keyboard_input() {
+        return;
+    }
+    let selected = ordered
+        .0
+        .iter()
+        .copied()
+        .filter(|e| matches!(selection.get(*e), Ok(s) if s.selected()))
+        .filter_map(|e| Some((e, ShapeRecord::capture(e, &records)?)))
+        .collect::<Vec<_>>();
+    // Ctrl + Shift + C converts objects to paths.
+    let copy = input.just_pressed(KeyCode::C) && !shift_pressed(&input);
+    let cut = input.just_pressed(KeyCode::X);
+    if (copy || cut) && !selected.is_empty() {
+        let shapes = selected.iter().map(|(_, r)| r.clone()).collect::<Vec<_>>();
+        copy_svg(&shapes);
+        clipboard.shapes = selected.clone();
+        // A cut shape is pasted where it was, a copied one next to it.
+        clipboard.pastes = u32::from(copy);
+        if cut {
+            let entities = selected.iter().map(|(e, _)| *e).collect::<Vec<_>>();
+            delete_shapes(
+                &mut commands,
+                &entities,
+                &ordered,
+                &records,
+                &mut history,
+                &mut changed,
+            );
+        }
+    } else if input.just_pressed(KeyCode::V) && !clipboard.shapes.is_empty() {
+        let offset = PASTE_OFFSET * clipboard.pastes as f32;
+        *pending_selection = paste_shapes(
+            &mut commands,
+            &mut ordered,
+            &clipboard.shapes,
+            offset,
+            &mut history,
+        );
+        clipboard.pastes += 1;
+    } else if input.just_pressed(KeyCode::D) && !selected.is_empty() {
+        *pending_selection = paste_shapes(
+            &mut commands,
+            &mut ordered,
+            &selected,
+            PASTE_OFFSET,
+            &mut history,
+        );
+    }
+}
@@ -0,0 +1,415 @@
+use crate::custom_shape::{CustomShapeRaw, ShapeSegment};
+use crate::helpers::{shape_bounds, BoundsQuery};
+use crate::history::{EditAction, HistoryEvent};
+use crate::keyboard_input::discard_shape;
+use crate::layers::Hidden;
+use crate::line_tool::LINE_WIDTH;
+use crate::stroke::Markers;
+use crate::{ChangedOrderEvent, MouseMovement, Moving, OrderedShapes, ShapeBase, Tool, ToolType};
+use bevy::prelude::*;
+use bevy::transform::TransformSystem;
+use bevy_mod_picking::PickableBundle;
+use bevy_prototype_lyon::prelude::tess::math::Point;
+use bevy_prototype_lyon::prelude::{DrawMode, GeometryBuilder, Path, ShapePath, StrokeMode};
+use iyes_loopless::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// How far the ends of a connector may drift before it is routed again.
+const ROUTE_TOLERANCE: f32 = 0.01;
+
+pub struct ConnectorPlugin;
+
+impl Plugin for ConnectorPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(
+            ConditionSet::new()
+                .run_if(should_connect)
+                .with_system(connector_handle_creation)
+                .with_system(connector_handle_update)
+                .into(),
+        )
+        // Targets can be grouped, so their global transforms have to be up to date.
+        .add_system_to_stage(
+            CoreStage::PostUpdate,
+            route_connectors.after(TransformSystem::TransformPropagate),
+        );
+    }
+}
+
+/// Point on the bounds of a shape that a connector is attached to.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AnchorPoint {
+    /// Follows the outline of the bounds, wherever it faces the other end.
+    Center,
+    Top,
+    Bottom,
+    Left,
+    Right,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl AnchorPoint {
+    pub const ALL: [(AnchorPoint, &'static str); 9] = [
+        (AnchorPoint::Center, "Center"),
+        (AnchorPoint::Top, "Top"),
+        (AnchorPoint::Bottom, "Bottom"),
+        (AnchorPoint::Left, "Left"),
+        (AnchorPoint::Right, "Right"),
+        (AnchorPoint::TopLeft, "Top left"),
+        (AnchorPoint::TopRight, "Top right"),
+        (AnchorPoint::BottomLeft, "Bottom left"),
+        (AnchorPoint::BottomRight, "Bottom right"),
+    ];
+
+    /// Position relative to the bounds, from -1 to 1 on both axes.
+    fn offset(&self) -> Vec2 {
+        match self {
+            AnchorPoint::Center => Vec2::ZERO,
+            AnchorPoint::Top => Vec2::Y,
+            AnchorPoint::Bottom => Vec2::NEG_Y,
+            AnchorPoint::Left => Vec2::NEG_X,
+            AnchorPoint::Right => Vec2::X,
+            AnchorPoint::TopLeft => Vec2::new(-1.0, 1.0),
+            AnchorPoint::TopRight => Vec2::ONE,
+            AnchorPoint::BottomLeft => Vec2::NEG_ONE,
+            AnchorPoint::BottomRight => Vec2::new(1.0, -1.0),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Routing {
+    Straight,
+    /// Horizontal and vertical lines with one or two bends.
+    Elbow,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Anchor {
+    pub target: Entity,
+    pub point: AnchorPoint,
+}
+
+/// Keeps the path of a line running between two shapes, whenever either of them changes.
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
+pub struct Connector {
+    pub start: Anchor,
+    pub end: Anchor,
+    pub routing: Routing,
+}
+
+impl Connector {
+    pub fn remap(&mut self, old: Entity, new: Entity) {
+        for anchor in [&mut self.start, &mut self.end] {
+            if anchor.target == old {
+                anchor.target = new;
+            }
+        }
+    }
+}
+
+/// Line that is being drawn from a shape, until it is released over another one.
+#[derive(Component)]
+struct PendingConnector {
+    start: Anchor,
+}
+
+/// Bounds of a shape in its own space, along with where that space is in the world.
+struct TargetBounds {
+    transform: GlobalTransform,
+    center: Vec2,
+    half: Vec2,
+}
+
+impl TargetBounds {
+    fn new(
+        target: Entity,
+        transform: &GlobalTransform,
+        meshes: &Assets<Mesh>,
+        bounds: &BoundsQuery,
+    ) -> Option<Self> {
+        let aabb = shape_bounds(target, meshes, bounds)?;
+        Some(Self {
+            transform: *transform,
+            center: Vec3::from(aabb.center).truncate(),
+            half: Vec3::from(aabb.half_extents).truncate(),
+        })
+    }
+
+    fn to_world(&self, p: Vec2) -> Vec2 {
+        self.transform.transform_point(p.extend(0.0)).truncate()
+    }
+
+    fn to_local(&self, p: Vec2) -> Vec2 {
+        let inverse = self.transform.affine().inverse();
+        inverse.transform_point3(p.extend(0.0)).truncate()
+    }
+
+    fn contains(&self, p: Vec2) -> bool {
+        let p = self.to_local(p) - self.center;
+        p.x.abs() <= self.half.x && p.y.abs() <= self.half.y
+    }
+
+    fn point(&self, point: AnchorPoint) -> Vec2 {
+        self.to_world(self.center + self.half * point.offset())
+    }
+
+    /// Where a connector coming from `toward` ends, center anchors move out onto the bounds.
+    fn attach(&self, point: AnchorPoint, toward: Vec2, routing: Routing) -> Vec2 {
+        if point != AnchorPoint::Center {
+            return self.point(point);
+        }
+        let d = self.to_local(toward) - self.center;
+        let local = match routing {
+            Routing::Straight => {
+                let t = (self.half.x / d.x.abs()).min(self.half.y / d.y.abs());
+                if t < 1.0 {
+                    d * t
+                } else {
+                    Vec2::ZERO
+                }
+            }
+            // The middle of the side facing the other end, so that the first line is straight.
+            Routing::Elbow if d.x.abs() * self.half.y >= d.y.abs() * self.half.x => {
+                Vec2::new(self.half.x.copysign(d.x), 0.0)
+            }
+            Routing::Elbow => Vec2::new(0.0, self.half.y.copysign(d.y)),
+        };
+        self.to_world(self.center + local)
+    }
+}
+
+/// Corners of a connector in world space, from its start to its end.
+fn route(connector: &Connector, start: &TargetBounds, end: &TargetBounds) -> Vec<Vec2> {
+    let (start_point, end_point) = (connector.start.point, connector.end.point);
+    let a = start.attach(start_point, end.point(end_point), connector.routing);
+    let b = end.attach(end_point, start.point(start_point), connector.routing);
+    if connector.routing == Routing::Straight {
+        return vec![a, b];
+    }
+    // An end leaves its shape horizontally when it is on the left or right side of it.
+    let horizontal = |p: Vec2, bounds: &TargetBounds, other: Vec2| {
+        let mut n = p - bounds.point(AnchorPoint::Center);
+        if n == Vec2::ZERO {
+            n = other - p;
+        }
+        n.x.abs() >= n.y.abs()
+    };
+    let mut points = match (horizontal(a, start, b), horizontal(b, end, a)) {
+        (true, true) => {
+            let x = (a.x + b.x) / 2.0;
+            vec![a, Vec2::new(x, a.y), Vec2::new(x, b.y), b]
+        }
+        (false, false) => {
+            let y = (a.y + b.y) / 2.0;
+            vec![a, Vec2::new(a.x, y), Vec2::new(b.x, y), b]
+        }
+        (true, false) => vec![a, Vec2::new(b.x, a.y), b],
+        (false, true) => vec![a, Vec2::new(a.x, b.y), b],
+    };
+    points.dedup();
+    points
+}
+
+type TargetQuery<'w, 's> = Query<
+    'w,
+    's,
+    &'static GlobalTransform,
+    (
+        With<ShapeBase>,
+        Without<Connector>,
+        Without<Moving>,
+        Without<Hidden>,
+    ),
+>;
+
+/// The anchor point closest to `position` on the topmost shape under it.
+fn anchor_at(
+    position: Vec2,
+    ordered: &OrderedShapes,
+    targets: &TargetQuery,
+    meshes: &Assets<Mesh>,
+    bounds: &BoundsQuery,
+) -> Option<(Anchor, Vec2)> {
+    ordered.0.iter().rev().find_map(|e| {
+        let target = TargetBounds::new(*e, targets.get(*e).ok()?, meshes, bounds)?;
+        if !target.contains(position) {
+            return None;
+        }
+        let (point, _) = AnchorPoint::ALL.iter().min_by(|(a, _), (b, _)| {
+            let distance = |p: &AnchorPoint| target.point(*p).distance(position);
+            distance(a).total_cmp(&distance(b))
+        })?;
+        let anchor = Anchor {
+            target: *e,
+            point: *point,
+        };
+        Some((anchor, target.point(*point)))
+    })
+}
+
+fn should_connect(tool: Res<Tool>) -> bool {
+    tool.tool == ToolType::Connector
+}
+
+#[allow(clippy::too_many_arguments)]
+fn connector_handle_creation(
+    mut commands: Commands,
+    mouse_input: Res<Input<MouseButton>>,
+    mouse: Res<MouseMovement>,
+    tool: Res<Tool>,
+    ordered: Res<OrderedShapes>,
+    meshes: Res<Assets<Mesh>>,
+    bounds: BoundsQuery,
+    targets: TargetQuery,
+    query: Query<&Moving>,
+) {
+    if !mouse_input.just_pressed(MouseButton::Left) || mouse.over_ui || query.get_single().is_ok() {
+        return;
+    }
+    if let Some((start, at)) = anchor_at(mouse.position, &ordered, &targets, &meshes, &bounds) {
+        let line = CustomShapeRaw {
+            segments: vec![ShapeSegment::Line(Point::zero())],
+            closed: false,
+            origin: Vec2::ZERO,
+            subpaths: vec![],
+        };
+        let color = Color::rgba_u8(tool.color[0], tool.color[1], tool.color[2], tool.color[3]);
+        let mut e = commands.spawn(GeometryBuilder::build_as(
+            &line,
+            DrawMode::Stroke(StrokeMode::new(color, LINE_WIDTH)),
+            Transform::from_translation(at.extend(0.0)),
+        ));
+        e.insert((
+            line,
+            ShapeBase {
+                name: None,
+                originx: Vec3::ZERO,
+            },
+            Moving { origin: at },
+            PendingConnector { start },
+        ));
+        if tool.markers != Markers::default() {
+            e.insert(tool.markers);
+        }
+    }
+}
+
+/// Follows the mouse until it is released, over a shape to connect to or anywhere else to give up.
+#[allow(clippy::too_many_arguments)]
+fn connector_handle_update(
+    mut commands: Commands,
+    mouse_input: Res<Input<MouseButton>>,
+    mouse: Res<MouseMovement>,
+    tool: Res<Tool>,
+    ordered: Res<OrderedShapes>,
+    meshes: Res<Assets<Mesh>>,
+    bounds: BoundsQuery,
+    targets: TargetQuery,
+    mut query: Query<(
+        Entity,
+        &PendingConnector,
+        &Moving,
+        &mut CustomShapeRaw,
+        &mut Path,
+    )>,
+    mut history: EventWriter<HistoryEvent>,
+    mut changed: EventWriter<ChangedOrderEvent>,
+) {
+    if let Ok((entity, pending, moving, mut line, mut path)) = query.get_single_mut() {
+        let to = mouse.position - moving.origin;
+        line.segments[0] = ShapeSegment::Line(Point::new(to.x, to.y));
+        *path = ShapePath::build_as(&*line);
+        if !mouse_input.just_released(MouseButton::Left) {
+            return;
+        }
+        match anchor_at(mouse.position, &ordered, &targets, &meshes, &bounds) {
+            Some((end, _)) if end.target != pending.start.target => {
+                commands
+                    .entity(entity)
+                    .remove::<Moving>()
+                    .remove::<PendingConnector>()
+                    .insert((
+                        Connector {
+                            start: pending.start,
+                            end,
+                            routing: tool.routing,
+                        },
+                        PickableBundle::default(),
+                    ));
+                history.send(HistoryEvent(EditAction::Created { entity }));
+            }
+            _ => {
+                discard_shape(&mut commands, entity, &mut changed);
+            }
+        }
+    }
+}
+
+/// Routes connectors again once their ends are no longer where their targets want them. The
+/// transform of a connector is left alone, its path is rebuilt in whatever space that gives it.
+fn route_connectors(
+    meshes: Res<Assets<Mesh>>,
+    bounds: BoundsQuery,
+    targets: Query<&GlobalTransform, With<ShapeBase>>,
+    mut connectors: Query<
+        (&Connector, &mut CustomShapeRaw, &mut Path, &GlobalTransform),
+        Without<Moving>,
+    >,
+) {
+    for (connector, mut line, mut path, transform) in connectors.iter_mut() {
+        let target = |anchor: &Anchor| {
+            TargetBounds::new(
+                anchor.target,
+                targets.get(anchor.target).ok()?,
+                &meshes,
+                &bounds,
+            )
+        };
+        // Connectors keep their last route while a target is deleted, it may come back.
+        let (start, end) = match (target(&connector.start), target(&connector.end)) {
+            (Some(start), Some(end)) => (start, end),
+            _ => continue,
+        };
+        let points = route(connector, &start, &end);
+        let current = std::iter::once(Point::zero())
+            .chain(line.segments.iter().map(|s| match *s {
+                ShapeSegment::Line(to)
+                | ShapeSegment::QuadraticBezier { to, .. }
+                | ShapeSegment::CubicBezier { to, .. } => to,
+            }))
+            .map(|p| {
+                let local = Vec2::new(p.x, p.y) + line.origin;
+                transform.transform_point(local.extend(0.0)).truncate()
+            })
+            .collect::<Vec<_>>();
+        let settled = line.subpaths.is_empty()
+            && current.len() == points.len()
+            && current
+                .iter()
+                .zip(points.iter())
+                .all(|(a, b)| a.distance(*b) <= ROUTE_TOLERANCE);
+        if settled {
+            continue;
+        }
+        let inverse = transform.affine().inverse();
+        let local = points
+            .iter()
+            .map(|p| inverse.transform_point3(p.extend(0.0)).truncate())
+            .collect::<Vec<_>>();
+        let origin = local[0];
+        *line = CustomShapeRaw {
+            segments: local[1..]
+                .iter()
+                .map(|p| ShapeSegment::Line(Point::new(p.x - origin.x, p.y - origin.y)))
+                .collect(),
+            closed: false,
+            origin,
+            subpaths: vec![],
+        };
+        *path = ShapePath::build_as(&*line);
+    }
+}
@@ -1,4 +1,5 @@
-use crate::helpers::{point_from_positions, rotate_around_pivot};
+use crate::helpers::{alt_pressed, point_from_positions, rotate_around_pivot};
+use crate::history::{EditAction, HistoryEvent};
 use crate::tess::geom::euclid;
 use crate::tess::geom::euclid::Size2D;
 use crate::ShapeSegment::*;
@@ -23,21 +24,42 @@ pub struct SerializedPoint2D<T, U> {
     #[doc(hidden)]
     pub _unit: PhantomData<U>,
 }
+/// Distance the mouse has to be dragged while placing a point before it gets Bezier handles.
+const HANDLE_THRESHOLD: f32 = 2.0;
+
+/// Handles of the point the pen tool is placing, relative to the start of the shape.
+#[derive(Component, Default)]
+pub struct PenHandles {
+    out_handle: Option<Point>,
+    in_handle: Point,
+}
+
 pub fn custom_shape_handle_creation(
+    mut pressed: Local<Vec2>,
     mut commands: Commands,
     mouse_input: Res<Input<MouseButton>>,
     mouse: Res<MouseMovement>,
     tool: Res<Tool>,
     query: Query<&Moving>,
 ) {
+    if mouse_input.just_pressed(MouseButton::Left) {
+        *pressed = mouse.snapped;
+    }
     if mouse_input.just_released(MouseButton::Left) && !mouse.over_ui && query.get_single().is_err()
     {
+        // Dragging while placing the first point pulls out its outgoing handle.
+        let out_handle = if pressed.distance(mouse.snapped) > HANDLE_THRESHOLD {
+            Some(point_from_positions(mouse.snapped, *pressed))
+        } else {
+            None
+        };
         commands
             .spawn(GeometryBuilder::build_as(
                 &CustomShapeRaw {
                     segments: vec![],
                     closed: false,
                     origin: Vec2::ZERO,
+                    subpaths: vec![],
                 },
                 DrawMode::Stroke(StrokeMode::color(Color::rgba_u8(
                     tool.color[0],
@@ -45,46 +67,80 @@ pub fn custom_shape_handle_creation(
                     tool.color[2],
                     tool.color[3],
                 ))),
-                Transform::from_translation(mouse.position.extend(0.0)),
+                Transform::from_translation(pressed.extend(0.0)),
             ))
             .insert((CustomShapeRaw {
                 segments: vec![Line(Point::zero())],
                 closed: false,
                 origin: Vec2::ZERO,
+                subpaths: vec![],
             },ShapeBase {
                 name: None,
                 originx: Vec3::ZERO,
             }
            , Moving {
-                origin: mouse.position,
+                origin: *pressed,
+            }, PenHandles {
+                out_handle,
+                in_handle: Point::zero(),
             }));
     }
 }
 
+/// Segment from the previous point to `to`, a line unless either side has a handle.
+fn pen_segment(shape: &CustomShapeRaw, handles: &PenHandles, to: Point) -> ShapeSegment {
+    let last = shape.segments.len() - 1;
+    let from = match last {
+        0 => Point::zero(),
+        _ => match shape.segments[last - 1] {
+            Line(p) | QuadraticBezier { to: p, .. } | CubicBezier { to: p, .. } => p,
+        },
+    };
+    if handles.out_handle.is_none() && handles.in_handle == to {
+        return Line(to);
+    }
+    CubicBezier {
+        ctrl: handles.out_handle.unwrap_or(from),
+        ctrl2: handles.in_handle,
+        to,
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn custom_shape_handle_update(
     mut orig: Local<Vec2>,
     mut commands: Commands,
     mouse_input: Res<Input<MouseButton>>,
+    input: Res<Input<KeyCode>>,
     mouse: Res<MouseMovement>,
     mut query: Query<(
         &mut Path,
         &mut DrawMode,
         &mut CustomShapeRaw,
+        &mut PenHandles,
         &Moving,
         Entity,
     )>,
     tool: Res<Tool>,
+    mut history: EventWriter<HistoryEvent>,
 ) {
-    if let Ok((mut path, mut draw_mode, mut custom_shape, moving, entity)) = query.get_single_mut()
+    if let Ok((mut path, mut draw_mode, mut custom_shape, mut handles, moving, entity)) =
+        query.get_single_mut()
     {
+        let last = custom_shape.segments.len() - 1;
         if mouse_input.just_pressed(MouseButton::Left) && !mouse.over_ui {
-            *orig = mouse.position;
+            *orig = mouse.snapped;
+            handles.in_handle = point_from_positions(*orig, moving.origin);
         }
 
         if mouse_input.just_released(MouseButton::Left) && !mouse.over_ui {
             let mut closed = false;
             if orig.distance(moving.origin) <= 10.0 {
                 closed = true;
+                // Snap the last point onto the start, keeping its handle in place.
+                let anchor = point_from_positions(*orig, moving.origin);
+                handles.in_handle -= anchor.to_vector();
+                custom_shape.segments[last] = pen_segment(&custom_shape, &handles, Point::zero());
                 *draw_mode = DrawMode::Fill(FillMode::color(Color::rgba_u8(
                     tool.color[0],
                     tool.color[1],
@@ -94,41 +150,74 @@ pub fn custom_shape_handle_update(
                 commands
                     .entity(entity)
                     .remove::<Moving>()
+                    .remove::<PenHandles>()
                     .insert(PickableBundle::default());
+                history.send(HistoryEvent(EditAction::Created { entity }));
             } else {
+                handles.out_handle = if orig.distance(mouse.snapped) > HANDLE_THRESHOLD {
+                    Some(point_from_positions(mouse.snapped, moving.origin))
+                } else {
+                    None
+                };
                 custom_shape
                     .segments
-                    .push(Line(point_from_positions(mouse.position, moving.origin)));
+                    .push(Line(point_from_positions(mouse.snapped, moving.origin)));
             }
             *path = ShapePath::build_as(&CustomShapeRaw {
                 segments: custom_shape.segments.clone(),
                 closed,
                 origin: custom_shape.origin,
+                subpaths: vec![],
             });
         } else if !mouse_input.pressed(MouseButton::Left) {
-            let last = custom_shape.segments.len() - 1;
-            custom_shape.segments[last] = Line(point_from_positions(mouse.position, moving.origin));
+            let to = point_from_positions(mouse.snapped, moving.origin);
+            handles.in_handle = to;
+            custom_shape.segments[last] = pen_segment(&custom_shape, &handles, to);
             *path = ShapePath::build_as(&CustomShapeRaw {
                 segments: custom_shape.segments.clone(),
                 closed: false,
                 origin: custom_shape.origin,
+                subpaths: vec![],
             });
         } else {
-            let last = custom_shape.segments.len() - 1;
-            custom_shape.segments[last] = QuadraticBezier {
-                ctrl: rotate_around_pivot(mouse.position, moving.origin, *orig),
-                to: point_from_positions(*orig, moving.origin),
-            };
+            // The incoming handle mirrors the dragged outgoing one unless Alt breaks the symmetry.
+            if !alt_pressed(&input) {
+                handles.in_handle = rotate_around_pivot(mouse.snapped, moving.origin, *orig);
+            }
+            let anchor = point_from_positions(*orig, moving.origin);
+            custom_shape.segments[last] = pen_segment(&custom_shape, &handles, anchor);
             *path = ShapePath::build_as(&custom_shape.clone());
         }
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Component)]
+#[derive(Debug, Clone, PartialEq, Component, Serialize, Deserialize)]
 pub struct CustomShapeRaw {
     pub segments: Vec<ShapeSegment>,
     pub closed: bool,
     pub origin: Vec2,
+    /// Further contours of a compound shape, holes depend on the fill rule.
+    pub subpaths: Vec<Subpath>,
+}
+
+/// Contour after the first one, with its start relative to `origin` like the segments.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Subpath {
+    #[serde(with = "SerializedPoint2D")]
+    pub start: Point,
+    pub segments: Vec<ShapeSegment>,
+    pub closed: bool,
+}
+
+impl CustomShapeRaw {
+    /// Every contour as its start, segments and whether it is closed, relative to `origin`.
+    pub fn contours(&self) -> impl Iterator<Item = (Point, &[ShapeSegment], bool)> {
+        std::iter::once((Point::zero(), &self.segments[..], self.closed)).chain(
+            self.subpaths
+                .iter()
+                .map(|s| (s.start, &s.segments[..], s.closed)),
+        )
+    }
 }
 
 #[allow(dead_code)]
@@ -152,18 +241,34 @@ pub enum ShapeSegment {
     },
 }
 
+impl ShapeSegment {
+    /// The same kind of segment with every point passed through `f`.
+    pub fn map(&self, f: impl Fn(Point) -> Point) -> ShapeSegment {
+        match *self {
+            Line(to) => Line(f(to)),
+            QuadraticBezier { ctrl, to } => QuadraticBezier { ctrl: f(ctrl), to: f(to) },
+            CubicBezier { ctrl, ctrl2, to } => CubicBezier {
+                ctrl: f(ctrl),
+                ctrl2: f(ctrl2),
+                to: f(to),
+            },
+        }
+    }
+}
+
 impl Geometry for CustomShapeRaw {
     fn add_geometry(&self, b: &mut Builder) {
-        let v = Point::from((self.origin.x, self.origin.y));
         let o = Size2D::from((self.origin.x, self.origin.y));
-        b.begin(v);
-        for segment in self.segments.iter() {
-            match *segment {
-                Line(end) => b.line_to(end + o),
-                QuadraticBezier { ctrl, to } => b.quadratic_bezier_to(ctrl + o, to + o),
-                CubicBezier { ctrl, ctrl2, to } => b.cubic_bezier_to(ctrl + o, ctrl2 + o, to + o),
-            };
+        for (start, segments, closed) in self.contours() {
+            b.begin(start + o);
+            for segment in segments.iter() {
+                match *segment {
+                    Line(end) => b.line_to(end + o),
+                    QuadraticBezier { ctrl, to } => b.quadratic_bezier_to(ctrl + o, to + o),
+                    CubicBezier { ctrl, ctrl2, to } => b.cubic_bezier_to(ctrl + o, ctrl2 + o, to + o),
+                };
+            }
+            b.end(closed);
         }
-        b.end(self.closed);
     }
 }
@@ -0,0 +1,600 @@
+use crate::connector::{Anchor, AnchorPoint, Connector, Routing};
+use crate::custom_shape::CustomShapeRaw;
+use crate::gradient::Gradient;
+use crate::group::ShapeGroup;
+use crate::helpers::ctrl_pressed;
+use crate::history::History;
+use crate::layers::{Hidden, Locked};
+use crate::stroke::{Dashes, Markers};
+use crate::{Moving, OrderedShapes, PrimitiveShape, ShapeBase};
+use bevy::ecs::query::ReadOnlyWorldQuery;
+use bevy::prelude::*;
+use bevy_mod_picking::{PickableBundle, PickableMesh};
+use bevy_prototype_lyon::prelude::{
+    DrawMode, FillMode, FillOptions, FillRule, GeometryBuilder, LineCap, LineJoin, ShapePath,
+    StrokeMode, StrokeOptions,
+};
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::fs;
+
+pub const DOCUMENT_VERSION: u32 = 2;
+const DOCUMENT_PATH: &str = "drawing.shapes";
+
+pub struct DocumentPlugin;
+
+impl Plugin for DocumentPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(handle_save_input)
+            .add_system(handle_load_input);
+    }
+}
+
+pub type ShapeRecordQuery<'a> = (
+    &'a ShapeBase,
+    &'a Transform,
+    Option<&'a DrawMode>,
+    Option<&'a CustomShapeRaw>,
+    Option<&'a PrimitiveShape>,
+    Option<&'a Children>,
+    Option<&'a Locked>,
+    Option<&'a Hidden>,
+    Option<&'a Gradient>,
+    Option<&'a Dashes>,
+    Option<&'a Markers>,
+    Option<&'a Connector>,
+);
+
+#[derive(Serialize, Deserialize)]
+pub struct Document {
+    pub version: u32,
+    pub shapes: Vec<ShapeRecord>,
+    pub connectors: Vec<ConnectorRecord>,
+}
+
+/// Connector between shapes, which are referred to by their index among all shapes of the
+/// document, with the shapes in a group before the group itself.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct ConnectorRecord {
+    pub shape: usize,
+    pub start: (usize, AnchorPoint),
+    pub end: (usize, AnchorPoint),
+    pub routing: Routing,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ShapeRecord {
+    pub name: Option<String>,
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+    /// Groups don't have a style of their own.
+    pub style: Option<StyleRecord>,
+    pub gradient: Option<Gradient>,
+    pub dashes: Option<Dashes>,
+    pub markers: Option<Markers>,
+    /// Refers to other entities, so documents store connectors separately.
+    #[serde(skip)]
+    pub connector: Option<Connector>,
+    pub geometry: GeometryRecord,
+    pub locked: bool,
+    pub hidden: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum GeometryRecord {
+    Custom(CustomShapeRaw),
+    Primitive(PrimitiveShape),
+    /// Child records with transforms relative to the group, bottom to top.
+    Group(Vec<ShapeRecord>),
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct FillRecord {
+    pub color: Color,
+    pub rule: FillRuleRecord,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FillRuleRecord {
+    EvenOdd,
+    NonZero,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct StrokeRecord {
+    pub color: Color,
+    pub width: f32,
+    pub cap: CapRecord,
+    pub join: JoinRecord,
+    pub miter_limit: f32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CapRecord {
+    Butt,
+    Square,
+    Round,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JoinRecord {
+    Miter,
+    Round,
+    Bevel,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub enum StyleRecord {
+    Fill(FillRecord),
+    Stroke(StrokeRecord),
+    Outlined {
+        fill: FillRecord,
+        outline: StrokeRecord,
+    },
+}
+
+#[derive(Debug)]
+pub enum DocumentError {
+    Io(std::io::Error),
+    Encoding(bincode::Error),
+    UnsupportedVersion(u32),
+}
+
+impl Display for DocumentError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DocumentError::Io(e) => write!(f, "{}", e),
+            DocumentError::Encoding(e) => write!(f, "{}", e),
+            DocumentError::UnsupportedVersion(v) => write!(
+                f,
+                "document version {} is not supported (expected {})",
+                v, DOCUMENT_VERSION
+            ),
+        }
+    }
+}
+
+impl From<std::io::Error> for DocumentError {
+    fn from(e: std::io::Error) -> Self {
+        DocumentError::Io(e)
+    }
+}
+
+impl From<bincode::Error> for DocumentError {
+    fn from(e: bincode::Error) -> Self {
+        DocumentError::Encoding(e)
+    }
+}
+
+impl From<&FillMode> for FillRecord {
+    fn from(fill: &FillMode) -> Self {
+        Self {
+            color: fill.color,
+            rule: fill.options.fill_rule.into(),
+        }
+    }
+}
+
+impl From<FillRule> for FillRuleRecord {
+    fn from(rule: FillRule) -> Self {
+        match rule {
+            FillRule::EvenOdd => FillRuleRecord::EvenOdd,
+            FillRule::NonZero => FillRuleRecord::NonZero,
+        }
+    }
+}
+
+impl From<FillRuleRecord> for FillRule {
+    fn from(rule: FillRuleRecord) -> Self {
+        match rule {
+            FillRuleRecord::EvenOdd => FillRule::EvenOdd,
+            FillRuleRecord::NonZero => FillRule::NonZero,
+        }
+    }
+}
+
+impl From<&StrokeMode> for StrokeRecord {
+    fn from(stroke: &StrokeMode) -> Self {
+        Self {
+            color: stroke.color,
+            width: stroke.options.line_width,
+            cap: stroke.options.start_cap.into(),
+            join: stroke.options.line_join.into(),
+            miter_limit: stroke.options.miter_limit,
+        }
+    }
+}
+
+impl From<LineCap> for CapRecord {
+    fn from(cap: LineCap) -> Self {
+        match cap {
+            LineCap::Butt => CapRecord::Butt,
+            LineCap::Square => CapRecord::Square,
+            LineCap::Round => CapRecord::Round,
+        }
+    }
+}
+
+impl From<CapRecord> for LineCap {
+    fn from(cap: CapRecord) -> Self {
+        match cap {
+            CapRecord::Butt => LineCap::Butt,
+            CapRecord::Square => LineCap::Square,
+            CapRecord::Round => LineCap::Round,
+        }
+    }
+}
+
+impl From<LineJoin> for JoinRecord {
+    fn from(join: LineJoin) -> Self {
+        match join {
+            LineJoin::Miter | LineJoin::MiterClip => JoinRecord::Miter,
+            LineJoin::Round => JoinRecord::Round,
+            LineJoin::Bevel => JoinRecord::Bevel,
+        }
+    }
+}
+
+impl From<JoinRecord> for LineJoin {
+    fn from(join: JoinRecord) -> Self {
+        match join {
+            JoinRecord::Miter => LineJoin::Miter,
+            JoinRecord::Round => LineJoin::Round,
+            JoinRecord::Bevel => LineJoin::Bevel,
+        }
+    }
+}
+
+impl From<&FillRecord> for FillMode {
+    fn from(fill: &FillRecord) -> Self {
+        FillMode {
+            options: FillOptions::default().with_fill_rule(fill.rule.into()),
+            color: fill.color,
+        }
+    }
+}
+
+impl From<&StrokeRecord> for StrokeMode {
+    fn from(stroke: &StrokeRecord) -> Self {
+        let mut mode = StrokeMode::new(stroke.color, stroke.width);
+        mode.options = mode
+            .options
+            .with_line_cap(stroke.cap.into())
+            .with_line_join(stroke.join.into())
+            // Lyon panics on limits below its minimum.
+            .with_miter_limit(stroke.miter_limit.max(StrokeOptions::MINIMUM_MITER_LIMIT));
+        mode
+    }
+}
+
+impl From<&DrawMode> for StyleRecord {
+    fn from(draw_mode: &DrawMode) -> Self {
+        match draw_mode {
+            DrawMode::Fill(fill) => StyleRecord::Fill(fill.into()),
+            DrawMode::Stroke(stroke) => StyleRecord::Stroke(stroke.into()),
+            DrawMode::Outlined {
+                fill_mode,
+                outline_mode,
+            } => StyleRecord::Outlined {
+                fill: fill_mode.into(),
+                outline: outline_mode.into(),
+            },
+        }
+    }
+}
+
+impl From<&StyleRecord> for DrawMode {
+    fn from(style: &StyleRecord) -> Self {
+        match style {
+            StyleRecord::Fill(fill) => DrawMode::Fill(fill.into()),
+            StyleRecord::Stroke(stroke) => DrawMode::Stroke(stroke.into()),
+            StyleRecord::Outlined { fill, outline } => DrawMode::Outlined {
+                fill_mode: fill.into(),
+                outline_mode: outline.into(),
+            },
+        }
+    }
+}
+
+impl ConnectorRecord {
+    /// `None` when either end is attached to a shape that isn't in `shapes`.
+    fn new(shape: usize, connector: &Connector, shapes: &[Entity]) -> Option<Self> {
+        let index = |anchor: &Anchor| {
+            let target = shapes.iter().position(|e| *e == anchor.target)?;
+            Some((target, anchor.point))
+        };
+        Some(Self {
+            shape,
+            start: index(&connector.start)?,
+            end: index(&connector.end)?,
+            routing: connector.routing,
+        })
+    }
+
+    fn connector(&self, shapes: &[Entity]) -> Option<Connector> {
+        let anchor = |(target, point): (usize, AnchorPoint)| {
+            Some(Anchor {
+                target: *shapes.get(target)?,
+                point,
+            })
+        };
+        Some(Connector {
+            start: anchor(self.start)?,
+            end: anchor(self.end)?,
+            routing: self.routing,
+        })
+    }
+}
+
+impl ShapeRecord {
+    pub fn capture<F: ReadOnlyWorldQuery>(
+        entity: Entity,
+        shapes: &Query<ShapeRecordQuery, F>,
+    ) -> Option<Self> {
+        let (
+            base,
+            transform,
+            draw_mode,
+            custom,
+            primitive,
+            children,
+            locked,
+            hidden,
+            gradient,
+            dashes,
+            markers,
+            connector,
+        ) = shapes.get(entity).ok()?;
+        let geometry = match (custom, primitive, children) {
+            (Some(custom), _, _) => GeometryRecord::Custom(custom.clone()),
+            (_, Some(primitive), _) => GeometryRecord::Primitive(primitive.clone()),
+            (_, _, Some(children)) => GeometryRecord::Group(
+                children
+                    .iter()
+                    .filter_map(|e| Self::capture(*e, shapes))
+                    .collect(),
+            ),
+            _ => return None,
+        };
+        Some(Self {
+            name: base.name.clone(),
+            translation: transform.translation,
+            rotation: transform.rotation,
+            scale: transform.scale,
+            style: draw_mode.map(StyleRecord::from),
+            gradient: gradient.cloned(),
+            dashes: dashes.cloned(),
+            markers: markers.copied(),
+            connector: connector.copied(),
+            geometry,
+            locked: locked.is_some(),
+            hidden: hidden.is_some(),
+        })
+    }
+
+    /// Drops the connectors of the shape and of every shape grouped under it.
+    pub fn detach_connectors(&mut self) {
+        self.connector = None;
+        if let GeometryRecord::Group(children) = &mut self.geometry {
+            children.iter_mut().for_each(Self::detach_connectors);
+        }
+    }
+
+    pub fn transform(&self) -> Transform {
+        Transform {
+            translation: self.translation,
+            rotation: self.rotation,
+            scale: self.scale,
+        }
+    }
+}
+
+/// Also returns every spawned entity, in the order `flatten_shapes` lists them.
+pub fn spawn_shape(commands: &mut Commands, record: &ShapeRecord) -> (Entity, Vec<Entity>) {
+    let mut spawned = vec![];
+    let entity = spawn_record(commands, record, true, &mut spawned);
+    (entity, spawned)
+}
+
+/// Shapes under a locked or hidden group can't be picked either. Every spawned entity is added
+/// to `spawned`, in the order `flatten_shapes` lists them.
+fn spawn_record(
+    commands: &mut Commands,
+    record: &ShapeRecord,
+    parent_pickable: bool,
+    spawned: &mut Vec<Entity>,
+) -> Entity {
+    let pickable = parent_pickable && !record.locked && !record.hidden;
+    let draw_mode = record.style.as_ref().map_or(
+        DrawMode::Fill(FillMode::color(Color::BLACK)),
+        DrawMode::from,
+    );
+    let mut entity = match &record.geometry {
+        GeometryRecord::Custom(custom) => {
+            let mut e = commands.spawn(GeometryBuilder::build_as(
+                custom,
+                draw_mode,
+                record.transform(),
+            ));
+            e.insert(custom.clone());
+            e
+        }
+        GeometryRecord::Primitive(primitive) => {
+            let mut e = commands.spawn(GeometryBuilder::build_as(
+                primitive,
+                draw_mode,
+                record.transform(),
+            ));
+            e.insert(primitive.clone());
+            e
+        }
+        GeometryRecord::Group(children) => {
+            let children = children
+                .iter()
+                .map(|child| spawn_record(commands, child, pickable, spawned))
+                .collect::<Vec<_>>();
+            let mut e = commands.spawn((
+                SpatialBundle::from_transform(record.transform()),
+                ShapeGroup,
+            ));
+            e.push_children(&children);
+            e
+        }
+    };
+    entity.insert((
+        ShapeBase {
+            name: record.name.clone(),
+            originx: Vec3::ZERO,
+        },
+        PickableBundle::default(),
+        Visibility {
+            is_visible: !record.hidden,
+        },
+    ));
+    if record.locked {
+        entity.insert(Locked);
+    }
+    if record.hidden {
+        entity.insert(Hidden);
+    }
+    if let Some(gradient) = &record.gradient {
+        entity.insert(gradient.clone());
+    }
+    if let Some(dashes) = &record.dashes {
+        entity.insert(dashes.clone());
+    }
+    if let Some(markers) = record.markers {
+        entity.insert(markers);
+    }
+    if let Some(connector) = record.connector {
+        entity.insert(connector);
+    }
+    if !pickable {
+        entity.remove::<PickableMesh>();
+    }
+    spawned.push(entity.id());
+    entity.id()
+}
+
+/// `entity` and every shape grouped under it, with the shapes in a group before the group.
+pub fn flatten_shapes<F: ReadOnlyWorldQuery>(
+    entity: Entity,
+    shapes: &Query<ShapeRecordQuery, F>,
+) -> Vec<Entity> {
+    let children = shapes
+        .get(entity)
+        .ok()
+        .and_then(|(_, _, _, _, _, children, ..)| children);
+    let mut flat = children
+        .into_iter()
+        .flatten()
+        .filter(|child| shapes.contains(**child))
+        .flat_map(|child| flatten_shapes(*child, shapes))
+        .collect::<Vec<_>>();
+    flat.push(entity);
+    flat
+}
+
+/// Swaps the geometry of an existing shape, also when it changes between custom and primitive.
+pub fn apply_geometry(commands: &mut Commands, entity: Entity, geometry: &GeometryRecord) {
+    let mut e = commands.entity(entity);
+    match geometry {
+        GeometryRecord::Custom(custom) => {
+            e.insert((custom.clone(), ShapePath::build_as(custom)))
+                .remove::<PrimitiveShape>();
+        }
+        GeometryRecord::Primitive(primitive) => {
+            e.insert((primitive.clone(), ShapePath::build_as(primitive)))
+                .remove::<CustomShapeRaw>();
+        }
+        GeometryRecord::Group(_) => {}
+    }
+}
+
+pub fn write_document(path: &str, document: &Document) -> Result<(), DocumentError> {
+    fs::write(path, bincode::serialize(document)?)?;
+    Ok(())
+}
+
+pub fn read_document(path: &str) -> Result<Document, DocumentError> {
+    let bytes = fs::read(path)?;
+    let version: u32 = bincode::deserialize(&bytes)?;
+    if version != DOCUMENT_VERSION {
+        return Err(DocumentError::UnsupportedVersion(version));
+    }
+    Ok(bincode::deserialize(&bytes)?)
+}
+
+fn handle_save_input(
+    input: Res<Input<KeyCode>>,
+    ordered: Res<OrderedShapes>,
+    shapes: Query<ShapeRecordQuery, Without<Moving>>,
+) {
+    if ctrl_pressed(&input) && input.just_pressed(KeyCode::S) {
+        let (entities, records): (Vec<Entity>, Vec<ShapeRecord>) = ordered
+            .0
+            .iter()
+            .filter_map(|e| Some((*e, ShapeRecord::capture(*e, &shapes)?)))
+            .unzip();
+        let flat = entities
+            .iter()
+            .flat_map(|e| flatten_shapes(*e, &shapes))
+            .collect::<Vec<_>>();
+        let connectors = flat
+            .iter()
+            .enumerate()
+            .filter_map(|(i, e)| {
+                let (.., connector) = shapes.get(*e).ok()?;
+                ConnectorRecord::new(i, connector?, &flat)
+            })
+            .collect();
+        let document = Document {
+            version: DOCUMENT_VERSION,
+            shapes: records,
+            connectors,
+        };
+        match write_document(DOCUMENT_PATH, &document) {
+            Ok(()) => info!(
+                "saved {} shapes to {}",
+                document.shapes.len(),
+                DOCUMENT_PATH
+            ),
+            Err(e) => error!("failed to save {}: {}", DOCUMENT_PATH, e),
+        }
+    }
+}
+
+fn handle_load_input(
+    mut commands: Commands,
+    input: Res<Input<KeyCode>>,
+    mut ordered: ResMut<OrderedShapes>,
+    mut history: ResMut<History>,
+    shapes: Query<Entity, (With<ShapeBase>, Without<Parent>)>,
+) {
+    if ctrl_pressed(&input) && input.just_pressed(KeyCode::O) {
+        match read_document(DOCUMENT_PATH) {
+            Ok(document) => {
+                for e in shapes.iter() {
+                    commands.entity(e).despawn_recursive();
+                }
+                let mut spawned = vec![];
+                ordered.0 = document
+                    .shapes
+                    .iter()
+                    .map(|record| spawn_record(&mut commands, record, true, &mut spawned))
+                    .collect();
+                for record in document.connectors.iter() {
+                    if let (Some(shape), Some(connector)) =
+                        (spawned.get(record.shape), record.connector(&spawned))
+                    {
+                        commands.entity(*shape).insert(connector);
+                    }
+                }
+                history.clear();
+                info!("loaded {} shapes from {}", ordered.0.len(), DOCUMENT_PATH);
+            }
+            Err(e) => error!("failed to load {}: {}", DOCUMENT_PATH, e),
+        }
+    }
+}
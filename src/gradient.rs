@@ -0,0 +1,373 @@
+use crate::history::{EditAction, HistoryEvent};
+use crate::{MouseMovement, ShapeBase, Tool, ToolType};
+use bevy::asset::load_internal_asset;
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::render::render_resource::{AsBindGroup, ShaderRef, ShaderType};
+use bevy::sprite::{Material2d, Material2dPlugin};
+use bevy_mod_picking::Selection;
+use bevy_prototype_lyon::prelude::tess::geom::{
+    CubicBezierSegment, LineSegment, QuadraticBezierSegment,
+};
+use bevy_prototype_lyon::prelude::tess::math::Box2D;
+use bevy_prototype_lyon::prelude::tess::path::PathEvent;
+use bevy_prototype_lyon::prelude::{
+    shapes, DrawMode, GeometryBuilder, Path, RectangleOrigin, ShapePath, StrokeMode,
+};
+use iyes_loopless::prelude::*;
+use serde::{Deserialize, Serialize};
+
+pub const MAX_STOPS: usize = 8;
+const HANDLE_SIZE: f32 = 10.0;
+const GRADIENT_SHADER_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 0x6a1f_3c2e_95d4_b870);
+
+pub struct GradientPlugin;
+
+impl Plugin for GradientPlugin {
+    fn build(&self, app: &mut App) {
+        load_internal_asset!(
+            app,
+            GRADIENT_SHADER_HANDLE,
+            "gradient.wgsl",
+            Shader::from_wgsl
+        );
+        app.add_plugin(Material2dPlugin::<GradientMaterial>::default())
+            .init_resource::<GradientEditor>()
+            .add_startup_system(spawn_gradient_overlay)
+            .add_system_set(
+                ConditionSet::new()
+                    .run_if(should_edit_gradients)
+                    .with_system(edit_gradient_handles)
+                    .into(),
+            )
+            .add_system(draw_gradient_handles)
+            .add_system_to_stage(CoreStage::PostUpdate, update_gradient_materials);
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GradientKind {
+    Linear,
+    Radial,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct GradientStop {
+    pub offset: f32,
+    pub color: Color,
+}
+
+/// Fill gradient relative to the bounds of the shape's outline, from `(0, 0)` in the bottom left
+/// corner to `(1, 1)` in the top right one, like SVG's `objectBoundingBox` units. A linear
+/// gradient runs from `start` to `end`, a radial one is centered on `start` and reaches its last
+/// stop at `end`.
+#[derive(Component, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Gradient {
+    pub kind: GradientKind,
+    pub start: Vec2,
+    pub end: Vec2,
+    /// Sorted by offset, between 0 and 1.
+    pub stops: Vec<GradientStop>,
+}
+
+impl Gradient {
+    /// A gradient from `color` to white across the bounds of a shape.
+    pub fn new(kind: GradientKind, color: Color) -> Self {
+        let start = match kind {
+            GradientKind::Linear => Vec2::new(0.0, 0.5),
+            GradientKind::Radial => Vec2::splat(0.5),
+        };
+        Self {
+            kind,
+            start,
+            end: Vec2::new(1.0, 0.5),
+            stops: vec![
+                GradientStop { offset: 0.0, color },
+                GradientStop {
+                    offset: 1.0,
+                    color: Color::WHITE,
+                },
+            ],
+        }
+    }
+
+    pub fn sort_stops(&mut self) {
+        self.stops.sort_by(|a, b| a.offset.total_cmp(&b.offset));
+    }
+}
+
+/// Bounds of the outline without its stroke, which don't move when the shape is re-centered.
+fn outline_bounds(path: &Path) -> Rect {
+    let bounds = path
+        .0
+        .iter()
+        .filter_map(|event| match event {
+            PathEvent::Begin { at } => Some(Box2D::new(at, at)),
+            PathEvent::Line { from, to } => Some(LineSegment { from, to }.bounding_box()),
+            PathEvent::Quadratic { from, ctrl, to } => {
+                Some(QuadraticBezierSegment { from, ctrl, to }.bounding_box())
+            }
+            PathEvent::Cubic {
+                from,
+                ctrl1,
+                ctrl2,
+                to,
+            } => Some(
+                CubicBezierSegment {
+                    from,
+                    ctrl1,
+                    ctrl2,
+                    to,
+                }
+                .bounding_box(),
+            ),
+            PathEvent::End { .. } => None,
+        })
+        .reduce(|a, b| a.union(&b));
+    bounds.map_or_else(Rect::default, |b| {
+        Rect::new(b.min.x, b.min.y, b.max.x, b.max.y)
+    })
+}
+
+/// Gradient point `p` in the local space of the shape.
+fn to_local(p: Vec2, bounds: Rect) -> Vec2 {
+    bounds.min + p * bounds.size()
+}
+
+fn from_local(local: Vec2, bounds: Rect) -> Vec2 {
+    (local - bounds.min) / bounds.size().max(Vec2::splat(f32::EPSILON))
+}
+
+#[derive(ShaderType, Clone, Debug, Default)]
+struct GradientUniform {
+    start: Vec2,
+    end: Vec2,
+    bounds_min: Vec2,
+    bounds_size: Vec2,
+    kind: u32,
+    stop_count: u32,
+    colors: [Vec4; MAX_STOPS],
+    offsets: [Vec4; MAX_STOPS / 4],
+}
+
+#[derive(AsBindGroup, TypeUuid, Clone, Debug)]
+#[uuid = "3f9c5a8e-2d41-4b7a-9e0c-7a51d2c4e8b6"]
+pub struct GradientMaterial {
+    #[uniform(0)]
+    gradient: GradientUniform,
+}
+
+impl Material2d for GradientMaterial {
+    fn vertex_shader() -> ShaderRef {
+        GRADIENT_SHADER_HANDLE.typed().into()
+    }
+
+    fn fragment_shader() -> ShaderRef {
+        GRADIENT_SHADER_HANDLE.typed().into()
+    }
+}
+
+impl GradientMaterial {
+    fn new(gradient: &Gradient, bounds: Rect) -> Self {
+        let mut uniform = GradientUniform {
+            start: gradient.start,
+            end: gradient.end,
+            bounds_min: bounds.min,
+            bounds_size: bounds.size(),
+            kind: match gradient.kind {
+                GradientKind::Linear => 0,
+                GradientKind::Radial => 1,
+            },
+            stop_count: gradient.stops.len().min(MAX_STOPS) as u32,
+            ..default()
+        };
+        for (i, stop) in gradient.stops.iter().take(MAX_STOPS).enumerate() {
+            uniform.colors[i] = Vec4::from(stop.color.as_linear_rgba_f32());
+            uniform.offsets[i / 4][i % 4] = stop.offset;
+        }
+        Self { gradient: uniform }
+    }
+}
+
+/// Replaces the color material of shapes with a gradient, and puts it back once it is removed.
+fn update_gradient_materials(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<GradientMaterial>>,
+    changed: Query<
+        (Entity, &Gradient, &Path, Option<&Handle<GradientMaterial>>),
+        Or<(Changed<Gradient>, Changed<Path>)>,
+    >,
+    removed: RemovedComponents<Gradient>,
+    shapes: Query<(), (With<ShapeBase>, Without<Gradient>)>,
+) {
+    for (e, gradient, path, handle) in changed.iter() {
+        let material = GradientMaterial::new(gradient, outline_bounds(path));
+        match handle.and_then(|handle| materials.get_mut(handle)) {
+            Some(existing) => *existing = material,
+            None => {
+                commands
+                    .entity(e)
+                    .insert(materials.add(material))
+                    .remove::<Handle<ColorMaterial>>();
+            }
+        }
+    }
+    for e in removed.iter() {
+        if shapes.contains(e) {
+            commands
+                .entity(e)
+                .insert(Handle::<ColorMaterial>::default())
+                .remove::<Handle<GradientMaterial>>();
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum GradientHandle {
+    Start,
+    End,
+}
+
+#[derive(Resource, Default)]
+struct GradientEditor {
+    dragging: Option<(Entity, GradientHandle, Gradient)>,
+}
+
+#[derive(Component)]
+struct GradientOverlay;
+
+fn should_edit_gradients(tool: Res<Tool>) -> bool {
+    tool.tool == ToolType::EditPoints
+}
+
+fn spawn_gradient_overlay(mut commands: Commands) {
+    commands
+        .spawn(GeometryBuilder::build_as(
+            &shapes::Rectangle {
+                extents: Vec2::ZERO,
+                origin: RectangleOrigin::Center,
+            },
+            DrawMode::Stroke(StrokeMode::color(Color::CYAN)),
+            Transform::from_translation(Vec3::new(0.0, 0.0, 160.0)),
+        ))
+        .insert(GradientOverlay);
+}
+
+/// The selected shape with a gradient, when there is exactly one.
+fn gradient_target<'a>(
+    shapes: impl Iterator<Item = (Entity, &'a Selection, &'a Gradient, &'a Transform, &'a Path)>,
+) -> Option<(Entity, &'a Gradient, &'a Transform, Rect)> {
+    let mut selected = shapes.filter(|(_, s, ..)| s.selected());
+    match (selected.next(), selected.next()) {
+        (Some((e, _, gradient, transform, path)), None) => {
+            Some((e, gradient, transform, outline_bounds(path)))
+        }
+        _ => None,
+    }
+}
+
+fn handle_position(
+    gradient: &Gradient,
+    transform: &Transform,
+    bounds: Rect,
+    handle: GradientHandle,
+) -> Vec2 {
+    let p = match handle {
+        GradientHandle::Start => gradient.start,
+        GradientHandle::End => gradient.end,
+    };
+    transform
+        .transform_point(to_local(p, bounds).extend(0.0))
+        .truncate()
+}
+
+fn edit_gradient_handles(
+    mut editor: ResMut<GradientEditor>,
+    mouse_input: Res<Input<MouseButton>>,
+    mouse: Res<MouseMovement>,
+    projection: Query<&OrthographicProjection>,
+    mut shapes: Query<(Entity, &Selection, &mut Gradient, &Transform, &Path)>,
+    mut history: EventWriter<HistoryEvent>,
+) {
+    if mouse_input.just_pressed(MouseButton::Left) && !mouse.over_ui {
+        let tolerance = projection.get_single().map_or(1.0, |p| p.scale) * HANDLE_SIZE;
+        let target = gradient_target(shapes.iter());
+        if let Some((e, gradient, transform, bounds)) = target {
+            editor.dragging = [GradientHandle::Start, GradientHandle::End]
+                .into_iter()
+                .map(|h| (h, handle_position(gradient, transform, bounds, h)))
+                .map(|(h, p)| (h, p.distance(mouse.position)))
+                .filter(|(_, distance)| *distance <= tolerance)
+                .min_by(|a, b| a.1.total_cmp(&b.1))
+                .map(|(h, _)| (e, h, gradient.clone()));
+        }
+    }
+    let (e, handle) = match &editor.dragging {
+        Some((e, handle, _)) => (*e, *handle),
+        None => return,
+    };
+    if let Ok((_, _, mut gradient, transform, path)) = shapes.get_mut(e) {
+        if mouse_input.pressed(MouseButton::Left) {
+            let local = transform
+                .compute_matrix()
+                .inverse()
+                .transform_point3(mouse.snapped.extend(0.0))
+                .truncate();
+            let p = from_local(local, outline_bounds(path));
+            match handle {
+                GradientHandle::Start => gradient.start = p,
+                GradientHandle::End => gradient.end = p,
+            }
+            return;
+        }
+        if let Some((_, _, before)) = editor.dragging.take() {
+            if before != *gradient {
+                history.send(HistoryEvent(EditAction::GradientChanged {
+                    entity: e,
+                    before: Some(before),
+                    after: Some(gradient.clone()),
+                }));
+            }
+        }
+    }
+    editor.dragging = None;
+}
+
+fn draw_gradient_handles(
+    tool: Res<Tool>,
+    projection: Query<&OrthographicProjection>,
+    shapes: Query<(Entity, &Selection, &Gradient, &Transform, &Path), Without<GradientOverlay>>,
+    mut overlay: Query<(&mut Path, &mut DrawMode), With<GradientOverlay>>,
+) {
+    let (mut path, mut draw_mode) = match overlay.get_single_mut() {
+        Ok(overlay) => overlay,
+        Err(_) => return,
+    };
+    let target = match gradient_target(shapes.iter()) {
+        Some(target) if tool.tool == ToolType::EditPoints => target,
+        _ => {
+            *path = ShapePath::build_as(&shapes::Rectangle {
+                extents: Vec2::ZERO,
+                origin: RectangleOrigin::Center,
+            });
+            return;
+        }
+    };
+    let (_, gradient, transform, bounds) = target;
+    let scale = projection.get_single().map_or(1.0, |p| p.scale);
+    let start = handle_position(gradient, transform, bounds, GradientHandle::Start);
+    let end = handle_position(gradient, transform, bounds, GradientHandle::End);
+    *path = ShapePath::new()
+        .add(&shapes::Line(start, end))
+        .add(&shapes::Circle {
+            radius: HANDLE_SIZE / 2.0 * scale,
+            center: start,
+        })
+        .add(&shapes::Rectangle {
+            extents: Vec2::splat(HANDLE_SIZE * scale),
+            origin: RectangleOrigin::CustomCenter(end),
+        })
+        .build();
+    *draw_mode = DrawMode::Stroke(StrokeMode::new(Color::CYAN, scale * 1.5));
+}
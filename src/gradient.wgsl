@@ -0,0 +1,87 @@
+#import bevy_sprite::mesh2d_view_bindings
+#import bevy_sprite::mesh2d_bindings
+
+// NOTE: Bindings must come before functions that use them!
+#import bevy_sprite::mesh2d_functions
+
+struct Gradient {
+    start: vec2<f32>,
+    end: vec2<f32>,
+    bounds_min: vec2<f32>,
+    bounds_size: vec2<f32>,
+    kind: u32,
+    stop_count: u32,
+    colors: array<vec4<f32>, 8>,
+    offsets: array<vec4<f32>, 2>,
+};
+
+@group(1) @binding(0)
+var<uniform> gradient: Gradient;
+
+struct Vertex {
+    @location(0) position: vec3<f32>,
+#ifdef VERTEX_UVS
+    // The first coordinate is 1 for the fill and 0 for an outline.
+    @location(2) uv: vec2<f32>,
+#endif
+#ifdef VERTEX_COLORS
+    @location(4) color: vec4<f32>,
+#endif
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) local_position: vec2<f32>,
+    @location(1) color: vec4<f32>,
+    @location(2) fill: f32,
+};
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    var out: VertexOutput;
+    let world_position = mesh2d_position_local_to_world(mesh.model, vec4<f32>(vertex.position, 1.0));
+    out.clip_position = mesh2d_position_world_to_clip(world_position);
+    out.local_position = vertex.position.xy;
+#ifdef VERTEX_COLORS
+    out.color = vertex.color;
+#else
+    out.color = vec4<f32>(1.0);
+#endif
+#ifdef VERTEX_UVS
+    out.fill = vertex.uv.x;
+#else
+    out.fill = 1.0;
+#endif
+    return out;
+}
+
+fn stop_offset(i: u32) -> f32 {
+    return gradient.offsets[i / 4u][i % 4u];
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Only the fill is painted with the gradient, an outline keeps its own color.
+    if (in.fill < 0.5) {
+        return in.color;
+    }
+    let p = (in.local_position - gradient.bounds_min) / max(gradient.bounds_size, vec2<f32>(0.0001));
+    let axis = gradient.end - gradient.start;
+    var t: f32;
+    if (gradient.kind == 0u) {
+        t = dot(p - gradient.start, axis) / max(dot(axis, axis), 0.0001);
+    } else {
+        t = length(p - gradient.start) / max(length(axis), 0.0001);
+    }
+    t = clamp(t, 0.0, 1.0);
+    var color = gradient.colors[0];
+    for (var i = 1u; i < gradient.stop_count; i = i + 1u) {
+        let from = stop_offset(i - 1u);
+        let to = stop_offset(i);
+        if (t > from) {
+            let f = clamp((t - from) / max(to - from, 0.0001), 0.0, 1.0);
+            color = mix(gradient.colors[i - 1u], gradient.colors[i], f);
+        }
+    }
+    return color;
+}
@@ -0,0 +1,137 @@
+use bevy::prelude::*;
+use bevy_prototype_lyon::prelude::{
+    shapes, DrawMode, GeometryBuilder, Path, RectangleOrigin, ShapePath, StrokeMode,
+};
+
+/// Lines closer than this on screen are merged into the next coarser level.
+const MIN_LINE_GAP: f32 = 8.0;
+
+pub struct GridPlugin;
+
+impl Plugin for GridPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Grid>()
+            .add_startup_system(spawn_grid)
+            .add_system_to_stage(CoreStage::PostUpdate, update_grid);
+    }
+}
+
+#[derive(Resource)]
+pub struct Grid {
+    pub visible: bool,
+    pub snap: bool,
+    pub spacing: f32,
+    pub subdivisions: u32,
+}
+
+impl Default for Grid {
+    fn default() -> Self {
+        Self {
+            visible: true,
+            snap: false,
+            spacing: 100.0,
+            subdivisions: 4,
+        }
+    }
+}
+
+impl Grid {
+    /// Distance between two grid intersections.
+    pub fn step(&self) -> f32 {
+        self.spacing / self.subdivisions.max(1) as f32
+    }
+
+    pub fn snap(&self, p: Vec2) -> Vec2 {
+        if self.snap && self.step() > 0.0 {
+            (p / self.step()).round() * self.step()
+        } else {
+            p
+        }
+    }
+}
+
+#[derive(Component)]
+struct GridLines {
+    major: bool,
+}
+
+fn spawn_grid(mut commands: Commands) {
+    // The camera doesn't draw anything below -0.1 and the bottom shape sits at 0.
+    for (major, color, z) in [
+        (false, Color::rgb(0.92, 0.92, 0.92), -0.06),
+        (true, Color::rgb(0.8, 0.8, 0.8), -0.03),
+    ] {
+        commands
+            .spawn(GeometryBuilder::build_as(
+                &shapes::Rectangle {
+                    extents: Vec2::ZERO,
+                    origin: RectangleOrigin::Center,
+                },
+                DrawMode::Stroke(StrokeMode::color(color)),
+                Transform::from_translation(Vec3::new(0.0, 0.0, z)),
+            ))
+            .insert(GridLines { major });
+    }
+}
+
+fn grid_lines(area: Rect, gap: f32) -> ShapePath {
+    let mut path = ShapePath::new();
+    let mut x = (area.min.x / gap).floor() * gap;
+    while x <= area.max.x {
+        path = path.add(&shapes::Line(
+            Vec2::new(x, area.min.y),
+            Vec2::new(x, area.max.y),
+        ));
+        x += gap;
+    }
+    let mut y = (area.min.y / gap).floor() * gap;
+    while y <= area.max.y {
+        path = path.add(&shapes::Line(
+            Vec2::new(area.min.x, y),
+            Vec2::new(area.max.x, y),
+        ));
+        y += gap;
+    }
+    path
+}
+
+fn update_grid(
+    grid: Res<Grid>,
+    mut last: Local<Option<(Rect, f32)>>,
+    camera: Query<(&Transform, &OrthographicProjection)>,
+    mut lines: Query<(&GridLines, &mut Path, &mut DrawMode, &mut Visibility)>,
+) {
+    let (cam_transform, cam) = match camera.get_single() {
+        Ok(camera) => camera,
+        Err(_) => return,
+    };
+    let half = Vec2::new(cam.right, cam.top) * cam.scale;
+    let area = Rect::from_center_half_size(cam_transform.translation.truncate(), half);
+    if !grid.is_changed() && *last == Some((area, cam.scale)) {
+        return;
+    }
+    *last = Some((area, cam.scale));
+    // Zooming out replaces the subdivisions by the major lines, and those by coarser ones.
+    let factor = grid.subdivisions.max(2) as f32;
+    let mut minor = grid.step();
+    let mut major = grid.spacing.max(minor);
+    while minor / cam.scale < MIN_LINE_GAP && minor > 0.0 {
+        minor = major;
+        major *= factor;
+    }
+    for (grid_lines_kind, mut path, mut draw_mode, mut visibility) in lines.iter_mut() {
+        visibility.is_visible = grid.visible && minor > 0.0;
+        if !visibility.is_visible {
+            continue;
+        }
+        let (gap, width) = if grid_lines_kind.major {
+            (major, 1.5)
+        } else {
+            (minor, 1.0)
+        };
+        *path = grid_lines(area, gap).build();
+        if let DrawMode::Stroke(stroke) = *draw_mode {
+            *draw_mode = DrawMode::Stroke(StrokeMode::new(stroke.color, width * cam.scale));
+        }
+    }
+}
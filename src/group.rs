@@ -0,0 +1,174 @@
+use crate::helpers::{ctrl_pressed, shape_bounds, shift_pressed, transformed_aabb, BoundsQuery};
+use crate::history::{EditAction, HistoryEvent};
+use crate::{MouseMovement, OrderedShapes, ShapeBase};
+use bevy::prelude::*;
+use bevy_mod_picking::{PickableBundle, Selection};
+
+pub struct GroupPlugin;
+
+impl Plugin for GroupPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(handle_group_input);
+    }
+}
+
+#[derive(Component)]
+pub struct ShapeGroup;
+
+/// The top-level shape an entity belongs to, the entity itself when it isn't grouped.
+pub fn root_shape(entity: Entity, parents: &Query<&Parent, With<ShapeBase>>) -> Entity {
+    let mut root = entity;
+    while let Ok(parent) = parents.get(root) {
+        root = parent.get();
+    }
+    root
+}
+
+/// Parents the given top-level shapes, ordered bottom to top, under a new group entity
+/// which takes the place of the topmost one in `OrderedShapes`.
+pub fn group_shapes(
+    commands: &mut Commands,
+    ordered: &mut OrderedShapes,
+    transform: Transform,
+    name: Option<String>,
+    children: &[(Entity, Transform)],
+) -> Entity {
+    let inverse = transform.compute_matrix().inverse();
+    for (e, child_transform) in children {
+        commands.entity(*e).insert(Transform::from_matrix(
+            inverse * child_transform.compute_matrix(),
+        ));
+    }
+    let entities = children.iter().map(|(e, _)| *e).collect::<Vec<_>>();
+    let top = ordered.0.iter().rposition(|e| entities.contains(e));
+    let index = top.map_or(ordered.0.len(), |top| {
+        ordered.0[..top]
+            .iter()
+            .filter(|e| !entities.contains(e))
+            .count()
+    });
+    ordered.0.retain(|e| !entities.contains(e));
+    let group = commands
+        .spawn((
+            SpatialBundle::from_transform(transform),
+            ShapeGroup,
+            ShapeBase {
+                name,
+                originx: Vec3::ZERO,
+            },
+            PickableBundle::default(),
+        ))
+        .push_children(&entities)
+        .id();
+    ordered.0.insert(index, group);
+    group
+}
+
+/// Moves the children of a group back to the top level, where the group was in `OrderedShapes`.
+pub fn ungroup_shape(
+    commands: &mut Commands,
+    ordered: &mut OrderedShapes,
+    group: Entity,
+    transform: &Transform,
+    children: &[(Entity, Transform)],
+) {
+    let entities = children.iter().map(|(e, _)| *e).collect::<Vec<_>>();
+    for (e, child_transform) in children {
+        commands
+            .entity(*e)
+            .insert(transform.mul_transform(*child_transform));
+    }
+    let index = ordered.0.iter().position(|e| *e == group);
+    if let Some(index) = index {
+        ordered.0.splice(index..=index, entities.iter().copied());
+    } else {
+        ordered.0.extend(entities.iter().copied());
+    }
+    commands.entity(group).remove_children(&entities).despawn();
+}
+
+#[allow(clippy::too_many_arguments)]
+fn handle_group_input(
+    mut commands: Commands,
+    mut pending_selection: Local<Vec<Entity>>,
+    input: Res<Input<KeyCode>>,
+    mouse: Res<MouseMovement>,
+    mut ordered: ResMut<OrderedShapes>,
+    mut selection: Query<&mut Selection, With<ShapeBase>>,
+    groups: Query<(&ShapeBase, &Children), With<ShapeGroup>>,
+    meshes: Res<Assets<Mesh>>,
+    shapes: BoundsQuery,
+    mut history: EventWriter<HistoryEvent>,
+) {
+    // New groups only exist once the commands of the previous frame were applied.
+    for e in pending_selection.drain(..) {
+        if let Ok(mut s) = selection.get_mut(e) {
+            s.set_selected(true);
+        }
+    }
+    if !ctrl_pressed(&input) || !input.just_pressed(KeyCode::G) || mouse.over_ui {
+        return;
+    }
+    let selected = ordered
+        .0
+        .iter()
+        .copied()
+        .filter(|e| matches!(selection.get(*e), Ok(s) if s.selected()))
+        .collect::<Vec<_>>();
+    if shift_pressed(&input) {
+        for group in selected {
+            let (base, children) = match groups.get(group) {
+                Ok(g) => g,
+                Err(_) => continue,
+            };
+            let transform = match shapes.get(group) {
+                Ok((_, _, transform)) => *transform,
+                Err(_) => continue,
+            };
+            let children = children
+                .iter()
+                .filter_map(|e| Some((*e, *shapes.get(*e).ok()?.2)))
+                .collect::<Vec<_>>();
+            ungroup_shape(&mut commands, &mut ordered, group, &transform, &children);
+            for (e, _) in children.iter() {
+                if let Ok(mut s) = selection.get_mut(*e) {
+                    s.set_selected(true);
+                }
+            }
+            history.send(HistoryEvent(EditAction::Ungrouped {
+                group,
+                transform,
+                name: base.name.clone(),
+                children: children.iter().map(|(e, _)| *e).collect(),
+            }));
+        }
+    } else if selected.len() > 1 {
+        let bounds = selected
+            .iter()
+            .filter_map(|e| {
+                let aabb = shape_bounds(*e, &meshes, &shapes)?;
+                Some(transformed_aabb(&aabb, shapes.get(*e).ok()?.2))
+            })
+            .reduce(|a, b| a.union(b))
+            .unwrap_or_default();
+        let children = selected
+            .iter()
+            .filter_map(|e| Some((*e, *shapes.get(*e).ok()?.2)))
+            .collect::<Vec<_>>();
+        let z = children.last().map_or(0.0, |(_, t)| t.translation.z);
+        let group = group_shapes(
+            &mut commands,
+            &mut ordered,
+            Transform::from_translation(bounds.center().extend(z)),
+            None,
+            &children,
+        );
+        for (e, _) in children.iter() {
+            if let Ok(mut s) = selection.get_mut(*e) {
+                s.set_selected(false);
+            }
+        }
+        pending_selection.push(group);
+        history.send(HistoryEvent(EditAction::Grouped { group }));
+    }
+}
@@ -1,5 +1,7 @@
-use crate::{Moving, ToolChanged};
+use crate::{Moving, ShapeBase, ToolChanged};
 use bevy::prelude::*;
+use bevy::render::primitives::Aabb;
+use bevy::sprite::Mesh2dHandle;
 use bevy_mod_picking::PickableBundle;
 use bevy_prototype_lyon::prelude::tess::math::Point;
 
@@ -29,6 +31,10 @@ pub fn handle_tool_change(
     }
 }
 
+pub fn ctrl_pressed(input: &Input<KeyCode>) -> bool {
+    input.any_pressed([KeyCode::LControl, KeyCode::RControl])
+}
+
 pub fn global_vec_to_local(vec: Vec2, rotation: f32) -> Vec2 {
     let len = vec.length();
     let angle = Vec2::new(1.0, 0.0).angle_between(vec);
@@ -40,3 +46,61 @@ pub fn global_vec_to_local(vec: Vec2, rotation: f32) -> Vec2 {
     }
     Vec2::new(x, y)
 }
+
+pub fn shift_pressed(input: &Input<KeyCode>) -> bool {
+    input.any_pressed([KeyCode::LShift, KeyCode::RShift])
+}
+
+pub fn alt_pressed(input: &Input<KeyCode>) -> bool {
+    input.any_pressed([KeyCode::LAlt, KeyCode::RAlt])
+}
+
+/// World space bounds of a mesh AABB after applying the shape transform.
+pub fn transformed_aabb(aabb: &Aabb, transform: &Transform) -> Rect {
+    let center = Vec3::from(aabb.center);
+    let half = Vec3::from(aabb.half_extents);
+    let corners = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)].map(|(x, y)| {
+        transform
+            .transform_point(center + half * Vec3::new(x, y, 0.0))
+            .truncate()
+    });
+    corners
+        .iter()
+        .fold(Rect::from_center_size(corners[0], Vec2::ZERO), |r, p| {
+            r.union_point(*p)
+        })
+}
+
+pub fn rects_overlap(a: Rect, b: Rect) -> bool {
+    a.min.x <= b.max.x && a.max.x >= b.min.x && a.min.y <= b.max.y && a.max.y >= b.min.y
+}
+
+pub type BoundsQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Option<&'static Mesh2dHandle>,
+        Option<&'static Children>,
+        &'static Transform,
+    ),
+    With<ShapeBase>,
+>;
+
+/// Bounds of a shape in its own space, for groups the union of all of their children.
+pub fn shape_bounds(entity: Entity, meshes: &Assets<Mesh>, shapes: &BoundsQuery) -> Option<Aabb> {
+    let (handle, children, _) = shapes.get(entity).ok()?;
+    if let Some(handle) = handle {
+        return meshes.get(&handle.0)?.compute_aabb();
+    }
+    let rect = children?
+        .iter()
+        .filter_map(|child| {
+            let aabb = shape_bounds(*child, meshes, shapes)?;
+            Some(transformed_aabb(&aabb, shapes.get(*child).ok()?.2))
+        })
+        .reduce(|a, b| a.union(b))?;
+    Some(Aabb::from_min_max(
+        rect.min.extend(0.0),
+        rect.max.extend(0.0),
+    ))
+}
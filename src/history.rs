@@ -0,0 +1,571 @@
+use crate::connector::Connector;
+use crate::document::{
+    apply_geometry, flatten_shapes, spawn_shape, GeometryRecord, ShapeRecord, ShapeRecordQuery,
+};
+use crate::gradient::Gradient;
+use crate::group::{group_shapes, ungroup_shape};
+use crate::helpers::{ctrl_pressed, shift_pressed};
+use crate::layers::{set_layer_flags, LayerFlagsQuery};
+use crate::stroke::{Dashes, Markers};
+use crate::{OrderedShapes, ShapeBase};
+use bevy::prelude::*;
+use bevy_egui::EguiContext;
+use bevy_prototype_lyon::prelude::DrawMode;
+
+const MAX_HISTORY: usize = 200;
+
+pub struct HistoryPlugin;
+
+impl Plugin for HistoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<History>()
+            .add_event::<HistoryEvent>()
+            .add_system(handle_history_input)
+            .add_system_to_stage(CoreStage::PostUpdate, record_history);
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum EditAction {
+    Created {
+        entity: Entity,
+    },
+    Deleted {
+        entity: Entity,
+        record: ShapeRecord,
+        index: usize,
+        /// `entity` and the shapes grouped under it, which are respawned as new entities too.
+        shapes: Vec<Entity>,
+    },
+    Transformed {
+        entity: Entity,
+        before: Transform,
+        after: Transform,
+    },
+    Restyled {
+        entity: Entity,
+        before: DrawMode,
+        after: DrawMode,
+    },
+    Reshaped {
+        entity: Entity,
+        before: GeometryRecord,
+        after: GeometryRecord,
+    },
+    Reordered {
+        before: Vec<Entity>,
+        after: Vec<Entity>,
+    },
+    Grouped {
+        group: Entity,
+    },
+    Ungrouped {
+        group: Entity,
+        transform: Transform,
+        name: Option<String>,
+        children: Vec<Entity>,
+    },
+    Renamed {
+        entity: Entity,
+        before: Option<String>,
+        after: Option<String>,
+    },
+    GradientChanged {
+        entity: Entity,
+        before: Option<Gradient>,
+        after: Option<Gradient>,
+    },
+    DashesChanged {
+        entity: Entity,
+        before: Option<Dashes>,
+        after: Option<Dashes>,
+    },
+    MarkersChanged {
+        entity: Entity,
+        before: Option<Markers>,
+        after: Option<Markers>,
+    },
+    Rerouted {
+        entity: Entity,
+        before: Connector,
+        after: Connector,
+    },
+    /// Whether the shape is locked and hidden.
+    LayerFlagsChanged {
+        entity: Entity,
+        before: (bool, bool),
+        after: (bool, bool),
+    },
+}
+
+/// Every action sent during the same frame ends up in the same undo step.
+pub struct HistoryEvent(pub EditAction);
+
+struct HistoryStep {
+    actions: Vec<EditAction>,
+    sealed: bool,
+}
+
+#[derive(Resource, Default)]
+pub struct History {
+    undo: Vec<HistoryStep>,
+    redo: Vec<HistoryStep>,
+}
+
+impl EditAction {
+    fn remap(&mut self, old: Entity, new: Entity) {
+        let replace = |e: &mut Entity| {
+            if *e == old {
+                *e = new;
+            }
+        };
+        match self {
+            EditAction::Deleted {
+                entity,
+                record,
+                shapes,
+                ..
+            } => {
+                replace(entity);
+                shapes.iter_mut().for_each(replace);
+                if let Some(connector) = &mut record.connector {
+                    connector.remap(old, new);
+                }
+            }
+            EditAction::Rerouted {
+                entity,
+                before,
+                after,
+            } => {
+                replace(entity);
+                before.remap(old, new);
+                after.remap(old, new);
+            }
+            EditAction::Created { entity }
+            | EditAction::Transformed { entity, .. }
+            | EditAction::Restyled { entity, .. }
+            | EditAction::Reshaped { entity, .. }
+            | EditAction::Renamed { entity, .. }
+            | EditAction::GradientChanged { entity, .. }
+            | EditAction::DashesChanged { entity, .. }
+            | EditAction::MarkersChanged { entity, .. }
+            | EditAction::LayerFlagsChanged { entity, .. }
+            | EditAction::Grouped { group: entity } => replace(entity),
+            EditAction::Reordered { before, after } => {
+                before.iter_mut().chain(after.iter_mut()).for_each(replace)
+            }
+            EditAction::Ungrouped {
+                group, children, ..
+            } => {
+                replace(group);
+                children.iter_mut().for_each(replace)
+            }
+        }
+    }
+}
+
+impl HistoryStep {
+    /// Continuous style, property and transform edits (dragging a color or width) are folded
+    /// into one step until the mouse button is released. Edits made without dragging, like
+    /// keyboard shortcuts or typed values, are never folded.
+    fn absorb(&mut self, actions: &[EditAction]) -> bool {
+        if self.sealed || self.actions.len() != actions.len() {
+            return false;
+        }
+        let matching = self.actions.iter().zip(actions).all(|pair| {
+            matches!(pair, (
+                EditAction::Restyled { entity: a, .. },
+                EditAction::Restyled { entity: b, .. },
+            ) | (
+                EditAction::Reshaped { entity: a, .. },
+                EditAction::Reshaped { entity: b, .. },
+            ) | (
+                EditAction::Transformed { entity: a, .. },
+                EditAction::Transformed { entity: b, .. },
+            ) | (
+                EditAction::GradientChanged { entity: a, .. },
+                EditAction::GradientChanged { entity: b, .. },
+            ) | (
+                EditAction::DashesChanged { entity: a, .. },
+                EditAction::DashesChanged { entity: b, .. },
+            ) if a == b)
+        });
+        if matching {
+            for (old, new) in self.actions.iter_mut().zip(actions) {
+                match (old, new) {
+                    (
+                        EditAction::Restyled { after, .. },
+                        EditAction::Restyled { after: new, .. },
+                    ) => *after = *new,
+                    (
+                        EditAction::Reshaped { after, .. },
+                        EditAction::Reshaped { after: new, .. },
+                    ) => *after = new.clone(),
+                    (
+                        EditAction::Transformed { after, .. },
+                        EditAction::Transformed { after: new, .. },
+                    ) => *after = *new,
+                    (
+                        EditAction::GradientChanged { after, .. },
+                        EditAction::GradientChanged { after: new, .. },
+                    ) => *after = new.clone(),
+                    (
+                        EditAction::DashesChanged { after, .. },
+                        EditAction::DashesChanged { after: new, .. },
+                    ) => *after = new.clone(),
+                    _ => {}
+                }
+            }
+        }
+        matching
+    }
+}
+
+impl History {
+    fn push(&mut self, actions: Vec<EditAction>, dragging: bool) {
+        self.redo.clear();
+        if let Some(last) = self.undo.last_mut() {
+            if dragging && last.absorb(&actions) {
+                return;
+            }
+            last.sealed = true;
+        }
+        self.undo.push(HistoryStep {
+            actions,
+            sealed: !dragging,
+        });
+        if self.undo.len() > MAX_HISTORY {
+            self.undo.remove(0);
+        }
+    }
+
+    fn remap(&mut self, old: Entity, new: Entity) {
+        for step in self.undo.iter_mut().chain(self.redo.iter_mut()) {
+            for action in step.actions.iter_mut() {
+                action.remap(old, new);
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+}
+
+fn record_history(
+    mut history: ResMut<History>,
+    mut events: EventReader<HistoryEvent>,
+    mouse_input: Res<Input<MouseButton>>,
+) {
+    let actions = events
+        .iter()
+        .map(|e| e.0.clone())
+        .collect::<Vec<EditAction>>();
+    if !actions.is_empty() {
+        // Edits made as the button is released still belong to the drag.
+        let dragging =
+            mouse_input.pressed(MouseButton::Left) || mouse_input.just_released(MouseButton::Left);
+        history.push(actions, dragging);
+    }
+    if mouse_input.just_released(MouseButton::Left) {
+        if let Some(last) = history.undo.last_mut() {
+            last.sealed = true;
+        }
+    }
+}
+
+fn handle_history_input(
+    mut commands: Commands,
+    input: Res<Input<KeyCode>>,
+    mut history: ResMut<History>,
+    mut ordered: ResMut<OrderedShapes>,
+    mut shapes: ParamSet<(
+        Query<ShapeRecordQuery>,
+        Query<&mut Transform>,
+        Query<(&Transform, &mut ShapeBase, Option<&Children>)>,
+        Query<&mut Connector>,
+        LayerFlagsQuery,
+        Query<&mut DrawMode>,
+    )>,
+    mut egui_context: ResMut<EguiContext>,
+) {
+    // Text fields have their own undo.
+    if egui_context.ctx_mut().wants_keyboard_input() {
+        return;
+    }
+    if !ctrl_pressed(&input) || !input.just_pressed(KeyCode::Z) {
+        return;
+    }
+    let redo = shift_pressed(&input);
+    let step = if redo {
+        history.redo.pop()
+    } else {
+        history.undo.pop()
+    };
+    let mut pending = match step {
+        Some(step) => step.actions,
+        None => return,
+    };
+    let mut reverted = vec![];
+    while let Some(action) = pending.pop() {
+        let (opposite, respawned) = match action {
+            EditAction::Created { entity } => {
+                let record = ShapeRecord::capture(entity, &shapes.p0());
+                let index = ordered.0.iter().position(|e| *e == entity);
+                match (record, index) {
+                    (Some(record), Some(index)) => {
+                        ordered.0.remove(index);
+                        commands.entity(entity).despawn_recursive();
+                        (
+                            EditAction::Deleted {
+                                entity,
+                                record,
+                                index,
+                                shapes: flatten_shapes(entity, &shapes.p0()),
+                            },
+                            vec![],
+                        )
+                    }
+                    _ => continue,
+                }
+            }
+            EditAction::Deleted {
+                record,
+                index,
+                shapes: old,
+                ..
+            } => {
+                let (new, spawned) = spawn_shape(&mut commands, &record);
+                let index = index.min(ordered.0.len());
+                ordered.0.insert(index, new);
+                (
+                    EditAction::Created { entity: new },
+                    old.into_iter().zip(spawned).collect(),
+                )
+            }
+            EditAction::Transformed {
+                entity,
+                before,
+                after,
+            } => {
+                if let Ok(mut transform) = shapes.p1().get_mut(entity) {
+                    *transform = before;
+                }
+                (
+                    EditAction::Transformed {
+                        entity,
+                        before: after,
+                        after: before,
+                    },
+                    vec![],
+                )
+            }
+            EditAction::Restyled {
+                entity,
+                before,
+                after,
+            } => {
+                if let Ok(mut draw_mode) = shapes.p5().get_mut(entity) {
+                    *draw_mode = before;
+                }
+                (
+                    EditAction::Restyled {
+                        entity,
+                        before: after,
+                        after: before,
+                    },
+                    vec![],
+                )
+            }
+            EditAction::Reshaped {
+                entity,
+                before,
+                after,
+            } => {
+                apply_geometry(&mut commands, entity, &before);
+                (
+                    EditAction::Reshaped {
+                        entity,
+                        before: after,
+                        after: before,
+                    },
+                    vec![],
+                )
+            }
+            EditAction::Reordered { before, after } => {
+                let existing = shapes.p0();
+                ordered.0 = before
+                    .iter()
+                    .copied()
+                    .filter(|e| existing.contains(*e))
+                    .collect();
+                (
+                    EditAction::Reordered {
+                        before: after,
+                        after: before,
+                    },
+                    vec![],
+                )
+            }
+            EditAction::Grouped { group } => {
+                let query = shapes.p2();
+                let (transform, base, children) = match query.get(group) {
+                    Ok((transform, base, Some(children))) => (*transform, base, children),
+                    _ => continue,
+                };
+                let name = base.name.clone();
+                let children = children
+                    .iter()
+                    .filter_map(|e| Some((*e, *query.get(*e).ok()?.0)))
+                    .collect::<Vec<_>>();
+                ungroup_shape(&mut commands, &mut ordered, group, &transform, &children);
+                (
+                    EditAction::Ungrouped {
+                        group,
+                        transform,
+                        name,
+                        children: children.into_iter().map(|(e, _)| e).collect(),
+                    },
+                    vec![],
+                )
+            }
+            EditAction::Ungrouped {
+                group,
+                transform,
+                name,
+                children,
+            } => {
+                let query = shapes.p2();
+                let children = children
+                    .iter()
+                    .filter_map(|e| Some((*e, *query.get(*e).ok()?.0)))
+                    .collect::<Vec<_>>();
+                let new = group_shapes(&mut commands, &mut ordered, transform, name, &children);
+                (EditAction::Grouped { group: new }, vec![(group, new)])
+            }
+            EditAction::Renamed {
+                entity,
+                before,
+                after,
+            } => {
+                if let Ok((_, mut base, _)) = shapes.p2().get_mut(entity) {
+                    base.name = before.clone();
+                }
+                (
+                    EditAction::Renamed {
+                        entity,
+                        before: after,
+                        after: before,
+                    },
+                    vec![],
+                )
+            }
+            EditAction::GradientChanged {
+                entity,
+                before,
+                after,
+            } => {
+                match &before {
+                    Some(gradient) => commands.entity(entity).insert(gradient.clone()),
+                    None => commands.entity(entity).remove::<Gradient>(),
+                };
+                (
+                    EditAction::GradientChanged {
+                        entity,
+                        before: after,
+                        after: before,
+                    },
+                    vec![],
+                )
+            }
+            EditAction::DashesChanged {
+                entity,
+                before,
+                after,
+            } => {
+                match &before {
+                    Some(dashes) => commands.entity(entity).insert(dashes.clone()),
+                    None => commands.entity(entity).remove::<Dashes>(),
+                };
+                (
+                    EditAction::DashesChanged {
+                        entity,
+                        before: after,
+                        after: before,
+                    },
+                    vec![],
+                )
+            }
+            EditAction::Rerouted {
+                entity,
+                before,
+                after,
+            } => {
+                commands.entity(entity).insert(before);
+                (
+                    EditAction::Rerouted {
+                        entity,
+                        before: after,
+                        after: before,
+                    },
+                    vec![],
+                )
+            }
+            EditAction::MarkersChanged {
+                entity,
+                before,
+                after,
+            } => {
+                match before {
+                    Some(markers) => commands.entity(entity).insert(markers),
+                    None => commands.entity(entity).remove::<Markers>(),
+                };
+                (
+                    EditAction::MarkersChanged {
+                        entity,
+                        before: after,
+                        after: before,
+                    },
+                    vec![],
+                )
+            }
+            EditAction::LayerFlagsChanged {
+                entity,
+                before,
+                after,
+            } => {
+                let (locked, hidden) = before;
+                set_layer_flags(&mut commands, entity, locked, hidden, &shapes.p4());
+                (
+                    EditAction::LayerFlagsChanged {
+                        entity,
+                        before: after,
+                        after: before,
+                    },
+                    vec![],
+                )
+            }
+        };
+        reverted.push(opposite);
+        for (old, new) in respawned {
+            history.remap(old, new);
+            // Connectors attached to the shape follow it to its new entity.
+            for mut connector in shapes.p3().iter_mut() {
+                connector.remap(old, new);
+            }
+            for action in pending.iter_mut().chain(reverted.iter_mut()) {
+                action.remap(old, new);
+            }
+        }
+    }
+    let step = HistoryStep {
+        actions: reverted,
+        sealed: true,
+    };
+    if redo {
+        history.undo.push(step);
+    } else {
+        history.redo.push(step);
+    }
+}
@@ -1,77 +1,147 @@
-use crate::custom_shape::CustomShapeRaw;
-use crate::KeyCode::{Delete, Escape, PageDown, PageUp, S};
-use crate::{ChangedOrderEvent, Moving, ShapeSegment};
+use crate::document::{flatten_shapes, ShapeRecord, ShapeRecordQuery};
+use crate::history::{EditAction, HistoryEvent};
+use crate::KeyCode::{Delete, Escape, PageDown, PageUp};
+use crate::{ChangedOrderEvent, Moving, OrderedShapes, Tool, ToolType};
 use bevy::prelude::*;
+use bevy_egui::EguiContext;
 use bevy_mod_picking::Selection;
-use std::fs;
+use std::cmp::Reverse;
 
 pub struct KeyboardInputPlugin;
 
 impl Plugin for KeyboardInputPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system(handle_save_input)
-            .add_system(handle_keyboard_input)
+        app.add_system(handle_keyboard_input)
             .add_system(handle_layer_change);
     }
 }
+#[allow(clippy::too_many_arguments)]
 fn handle_keyboard_input(
     mut commands: Commands,
     input: Res<Input<KeyCode>>,
     removal_query: Query<(Entity, &Selection)>,
     cancel_query: Query<Entity, With<Moving>>,
     mut changed: EventWriter<ChangedOrderEvent>,
+    mut history: EventWriter<HistoryEvent>,
+    ordered: Res<OrderedShapes>,
+    records: Query<ShapeRecordQuery>,
+    tool: Res<Tool>,
+    mut egui_context: ResMut<EguiContext>,
 ) {
-    if input.just_pressed(Delete) {
-        for e in removal_query.iter().filter_map(|(e, n)| {
-            if n.selected() {
-                return Some(e);
-            }
-            None
-        }) {
-            changed.send(ChangedOrderEvent {
-                entity: e,
-                change_up: false,
-                removed: true,
-            });
-            commands.entity(e).despawn();
-        }
+    // Keys typed into a text field, like a shape being renamed, aren't shortcuts.
+    if egui_context.ctx_mut().wants_keyboard_input() {
+        return;
+    }
+    // While editing points Delete removes the selected node instead.
+    if input.just_pressed(Delete) && tool.tool != ToolType::EditPoints {
+        let selected = removal_query
+            .iter()
+            .filter_map(|(e, n)| {
+                if n.selected() {
+                    return Some(e);
+                }
+                None
+            })
+            .collect::<Vec<_>>();
+        delete_shapes(
+            &mut commands,
+            &selected,
+            &ordered,
+            &records,
+            &mut history,
+            &mut changed,
+        );
     }
     if input.just_pressed(Escape) {
         for e in cancel_query.iter() {
-            commands.entity(e).despawn();
+            discard_shape(&mut commands, e, &mut changed);
+        }
+    }
+}
+
+/// Despawns the given top-level shapes, recording them so that they can be restored.
+pub fn delete_shapes(
+    commands: &mut Commands,
+    entities: &[Entity],
+    ordered: &OrderedShapes,
+    records: &Query<ShapeRecordQuery>,
+    history: &mut EventWriter<HistoryEvent>,
+    changed: &mut EventWriter<ChangedOrderEvent>,
+) {
+    let mut removed = entities
+        .iter()
+        .filter_map(|e| Some((*e, ordered.0.iter().position(|o| o == e)?)))
+        .collect::<Vec<(Entity, usize)>>();
+    // Undo re-inserts in reverse order, so the highest index has to go first.
+    removed.sort_by_key(|(_, index)| Reverse(*index));
+    for (e, index) in removed {
+        if let Some(record) = ShapeRecord::capture(e, records) {
+            history.send(HistoryEvent(EditAction::Deleted {
+                entity: e,
+                record,
+                index,
+                shapes: flatten_shapes(e, records),
+            }));
         }
+        changed.send(ChangedOrderEvent {
+            entity: e,
+            change_up: false,
+            removed: true,
+        });
+        commands.entity(e).despawn_recursive();
     }
 }
 
+/// Despawns a shape that is still being drawn, which leaves nothing to undo.
+pub fn discard_shape(
+    commands: &mut Commands,
+    entity: Entity,
+    changed: &mut EventWriter<ChangedOrderEvent>,
+) {
+    changed.send(ChangedOrderEvent {
+        entity,
+        change_up: false,
+        removed: true,
+    });
+    commands.entity(entity).despawn();
+}
+
 fn handle_layer_change(
     input: Res<Input<KeyCode>>,
     layer_query: Query<(&Selection, Entity)>,
+    ordered: Res<OrderedShapes>,
     mut changed: EventWriter<ChangedOrderEvent>,
 ) {
     let factor = input.just_pressed(PageUp) as i8 - input.just_pressed(PageDown) as i8;
     if factor != 0 {
-        if let Some(e) = layer_query
+        let mut selected = ordered
+            .0
             .iter()
-            .filter_map(|(s, e)| if s.selected() { Some(e) } else { None })
-            .next()
-        {
-            changed.send(ChangedOrderEvent {
-                entity: e,
-                change_up: factor == 1,
-                removed: false,
-            })
+            .enumerate()
+            .filter(|(_, e)| matches!(layer_query.get(**e), Ok((s, _)) if s.selected()))
+            .map(|(i, e)| (i as isize, *e))
+            .collect::<Vec<_>>();
+        if factor == 1 {
+            selected.reverse();
+        }
+        // Shapes already pressed against the top (or bottom) stay there, the rest move by one.
+        let step = factor as isize;
+        let mut limit = if factor == 1 {
+            ordered.0.len() as isize - 1
+        } else {
+            0
+        };
+        for (index, e) in selected {
+            if index != limit {
+                changed.send(ChangedOrderEvent {
+                    entity: e,
+                    change_up: factor == 1,
+                    removed: false,
+                });
+                limit = index;
+            } else {
+                limit = index - step;
+            }
         }
-    }
-}
-
-fn handle_save_input(input: Res<Input<KeyCode>>, shapes: Query<&CustomShapeRaw>) {
-    if input.pressed(KeyCode::LControl) && input.just_pressed(S) {
-        info!("saving");
-        let items = shapes
-            .iter()
-            .map(|c| &c.segments)
-            .collect::<Vec<&Vec<ShapeSegment>>>();
-        let encoded: Vec<u8> = bincode::serialize(&items).unwrap();
-        fs::write("foo.txt", encoded).expect("Unable to write file");
     }
 }
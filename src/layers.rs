@@ -0,0 +1,121 @@
+use crate::history::{EditAction, HistoryEvent};
+use crate::{MouseMovement, OrderedShapes, ShapeBase};
+use bevy::prelude::*;
+use bevy_mod_picking::{PickableMesh, Selection};
+
+pub struct LayersPlugin;
+
+impl Plugin for LayersPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(handle_stack_input);
+    }
+}
+
+/// Locked shapes still render but can't be picked, neither can the shapes grouped under them.
+#[derive(Component)]
+pub struct Locked;
+
+/// Hidden shapes aren't rendered, picked or exported.
+#[derive(Component)]
+pub struct Hidden;
+
+pub type LayerFlagsQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Option<&'static Children>,
+        Option<&'static Locked>,
+        Option<&'static Hidden>,
+    ),
+    With<ShapeBase>,
+>;
+
+pub fn set_layer_flags(
+    commands: &mut Commands,
+    e: Entity,
+    locked: bool,
+    hidden: bool,
+    shapes: &LayerFlagsQuery,
+) {
+    let mut entity = commands.entity(e);
+    if locked {
+        entity.insert(Locked);
+    } else {
+        entity.remove::<Locked>();
+    }
+    if hidden {
+        entity.insert(Hidden);
+    } else {
+        entity.remove::<Hidden>();
+    }
+    entity.insert(Visibility {
+        is_visible: !hidden,
+    });
+    let children = shapes.get(e).ok().and_then(|(children, ..)| children);
+    for child in children.into_iter().flatten() {
+        set_pickable(commands, *child, !locked && !hidden, shapes);
+    }
+    set_pickable_mesh(commands, e, !locked && !hidden);
+}
+
+fn set_pickable(commands: &mut Commands, e: Entity, parent: bool, shapes: &LayerFlagsQuery) {
+    if let Ok((children, locked, hidden)) = shapes.get(e) {
+        let pickable = parent && locked.is_none() && hidden.is_none();
+        set_pickable_mesh(commands, e, pickable);
+        for child in children.into_iter().flatten() {
+            set_pickable(commands, *child, pickable, shapes);
+        }
+    }
+}
+
+fn set_pickable_mesh(commands: &mut Commands, e: Entity, pickable: bool) {
+    if pickable {
+        commands.entity(e).insert(PickableMesh);
+    } else {
+        commands.entity(e).remove::<PickableMesh>();
+    }
+}
+
+/// Moves `entities` to the top (or bottom) of the stack, keeping their relative order.
+pub fn restack(ordered: &mut OrderedShapes, entities: &[Entity], front: bool) {
+    let (mut moved, rest): (Vec<Entity>, Vec<Entity>) = ordered
+        .0
+        .iter()
+        .copied()
+        .partition(|e| entities.contains(e));
+    ordered.0 = if front {
+        let mut stack = rest;
+        stack.append(&mut moved);
+        stack
+    } else {
+        moved.extend(rest);
+        moved
+    };
+}
+
+/// Home brings the selected shapes to the front, End sends them to the back.
+fn handle_stack_input(
+    input: Res<Input<KeyCode>>,
+    mouse: Res<MouseMovement>,
+    selection: Query<(Entity, &Selection), (With<ShapeBase>, Without<Parent>)>,
+    mut ordered: ResMut<OrderedShapes>,
+    mut history: EventWriter<HistoryEvent>,
+) {
+    let front = input.just_pressed(KeyCode::Home);
+    if !(front || input.just_pressed(KeyCode::End)) || mouse.over_ui {
+        return;
+    }
+    let selected = selection
+        .iter()
+        .filter(|(_, s)| s.selected())
+        .map(|(e, _)| e)
+        .collect::<Vec<_>>();
+    let before = ordered.0.clone();
+    restack(&mut ordered, &selected, front);
+    if ordered.0 != before {
+        history.send(HistoryEvent(EditAction::Reordered {
+            before,
+            after: ordered.0.clone(),
+        }));
+    }
+}
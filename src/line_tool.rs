@@ -0,0 +1,128 @@
+use crate::custom_shape::{CustomShapeRaw, ShapeSegment};
+use crate::helpers::{point_from_positions, shift_pressed};
+use crate::history::{EditAction, HistoryEvent};
+use crate::keyboard_input::discard_shape;
+use crate::stroke::Markers;
+use crate::{ChangedOrderEvent, MouseMovement, Moving, ShapeBase, Tool};
+use bevy::prelude::*;
+use bevy_mod_picking::PickableBundle;
+use bevy_prototype_lyon::prelude::tess::math::{Point, Vector};
+use bevy_prototype_lyon::prelude::{DrawMode, GeometryBuilder, Path, ShapePath, StrokeMode};
+
+/// Distance the mouse has to be dragged from the first point to draw a single line right away.
+const DRAG_THRESHOLD: f32 = 2.0;
+/// Stroke width of new lines, wide enough for their markers to be visible.
+pub const LINE_WIDTH: f32 = 2.0;
+
+pub fn line_handle_creation(
+    mut commands: Commands,
+    mouse_input: Res<Input<MouseButton>>,
+    mouse: Res<MouseMovement>,
+    tool: Res<Tool>,
+    query: Query<&Moving>,
+) {
+    if mouse_input.just_pressed(MouseButton::Left) && !mouse.over_ui && query.get_single().is_err()
+    {
+        let line = CustomShapeRaw {
+            segments: vec![ShapeSegment::Line(Point::zero())],
+            closed: false,
+            origin: Vec2::ZERO,
+            subpaths: vec![],
+        };
+        let color = Color::rgba_u8(tool.color[0], tool.color[1], tool.color[2], tool.color[3]);
+        let mut e = commands.spawn(GeometryBuilder::build_as(
+            &line,
+            DrawMode::Stroke(StrokeMode::new(color, LINE_WIDTH)),
+            Transform::from_translation(mouse.snapped.extend(0.0)),
+        ));
+        e.insert((
+            line,
+            ShapeBase {
+                name: None,
+                originx: Vec3::ZERO,
+            },
+            Moving {
+                origin: mouse.snapped,
+            },
+        ));
+        if tool.markers != Markers::default() {
+            e.insert(tool.markers);
+        }
+    }
+}
+
+/// `to` turned around `from` onto the closest multiple of 15 degrees.
+fn constrain_angle(from: Point, to: Point) -> Point {
+    let v = to - from;
+    let step = std::f32::consts::PI / 12.0;
+    let angle = (v.y.atan2(v.x) / step).round() * step;
+    from + Vector::new(angle.cos(), angle.sin()) * v.length()
+}
+
+/// Dragging out the first point draws a single line. Otherwise every click adds a point, until
+/// the last point is clicked again, Enter is pressed or the right mouse button is clicked.
+#[allow(clippy::too_many_arguments)]
+pub fn line_handle_update(
+    mut pressed: Local<bool>,
+    mut commands: Commands,
+    mouse_input: Res<Input<MouseButton>>,
+    input: Res<Input<KeyCode>>,
+    mouse: Res<MouseMovement>,
+    mut query: Query<(&mut Path, &mut CustomShapeRaw, &Moving, Entity)>,
+    mut history: EventWriter<HistoryEvent>,
+    mut changed: EventWriter<ChangedOrderEvent>,
+) {
+    if let Ok((mut path, mut line, moving, entity)) = query.get_single_mut() {
+        let last = line.segments.len() - 1;
+        let from = match last {
+            0 => Point::zero(),
+            _ => match line.segments[last - 1] {
+                ShapeSegment::Line(p)
+                | ShapeSegment::QuadraticBezier { to: p, .. }
+                | ShapeSegment::CubicBezier { to: p, .. } => p,
+            },
+        };
+        let mut to = point_from_positions(mouse.snapped, moving.origin);
+        if shift_pressed(&input) {
+            to = constrain_angle(from, to);
+        }
+        line.segments[last] = ShapeSegment::Line(to);
+        if mouse_input.just_pressed(MouseButton::Left) && !mouse.over_ui {
+            *pressed = true;
+        }
+        let mut finished = false;
+        if mouse_input.just_released(MouseButton::Left) && !mouse.over_ui {
+            // The press that started the line happened before it existed.
+            let dragged = (to - from).length() > DRAG_THRESHOLD;
+            if !*pressed {
+                finished = dragged;
+            } else if dragged {
+                line.segments.push(ShapeSegment::Line(to));
+            } else {
+                line.segments.pop();
+                finished = true;
+            }
+            *pressed = false;
+        } else if input.just_pressed(KeyCode::Return)
+            || mouse_input.just_pressed(MouseButton::Right)
+        {
+            line.segments.pop();
+            finished = true;
+        }
+        if finished {
+            *pressed = false;
+        }
+        if finished && line.segments.is_empty() {
+            discard_shape(&mut commands, entity, &mut changed);
+            return;
+        }
+        *path = ShapePath::build_as(&*line);
+        if finished {
+            commands
+                .entity(entity)
+                .remove::<Moving>()
+                .insert(PickableBundle::default());
+            history.send(HistoryEvent(EditAction::Created { entity }));
+        }
+    }
+}
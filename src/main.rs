@@ -1,16 +1,47 @@
+mod align;
+mod clipboard;
+mod connector;
 mod custom_shape;
+mod document;
+mod gradient;
+mod grid;
+mod group;
 mod helpers;
+mod history;
 mod keyboard_input;
+mod layers;
+mod line_tool;
+mod naming;
+mod node_editor;
 mod overlap_order;
+mod path_ops;
 mod picking_helpers;
+mod selection;
 mod shape_transformation;
+mod snapping;
+mod stroke;
+mod svg_export;
+mod svg_import;
 mod ui;
 
 use crate::custom_shape::{custom_shape_handle_creation, custom_shape_handle_update, ShapeSegment};
+use crate::align::AlignPlugin;
+use crate::clipboard::ClipboardPlugin;
+use crate::connector::{ConnectorPlugin, Routing};
+use crate::document::DocumentPlugin;
+use crate::gradient::GradientPlugin;
+use crate::grid::{Grid, GridPlugin};
+use crate::group::GroupPlugin;
+use crate::history::{EditAction, HistoryEvent, HistoryPlugin};
 use crate::picking_helpers::{
     spawn_highlight_rectangle, CustomPickingPlugins,
 };
+use crate::selection::SelectionPlugin;
 use crate::shape_transformation::{update_origin, ShapeTransformPlugin};
+use crate::snapping::SnappingPlugin;
+use crate::stroke::{Marker, Markers, StrokePlugin};
+use crate::svg_export::SvgExportPlugin;
+use crate::svg_import::SvgImportPlugin;
 use crate::ui::UIPlugin;
 use bevy::input::mouse::{MouseMotion, MouseWheel};
 use bevy::pbr::wireframe::{WireframeConfig, WireframePlugin};
@@ -20,11 +51,21 @@ use bevy_egui::EguiPlugin;
 //use bevy_inspector_egui::WorldInspectorPlugin;
 use crate::helpers::{global_vec_to_local, handle_tool_change};
 use crate::keyboard_input::KeyboardInputPlugin;
+use crate::layers::LayersPlugin;
+use crate::line_tool::{line_handle_creation, line_handle_update};
+use crate::naming::NamingPlugin;
+use crate::node_editor::NodeEditorPlugin;
+use crate::path_ops::PathOpsPlugin;
 use crate::overlap_order::{apply_overlap_order, calculate_overlap_order};
 use crate::CoreStage::{Last, PostUpdate};
 use bevy_mod_picking::{PickableBundle, PickingCameraBundle};
+use bevy_prototype_lyon::prelude::tess::math::{Box2D, Point};
+use bevy_prototype_lyon::prelude::tess::path::builder::BorderRadii;
+use bevy_prototype_lyon::prelude::tess::path::path::Builder;
+use bevy_prototype_lyon::prelude::tess::path::Winding;
 use bevy_prototype_lyon::prelude::*;
 use iyes_loopless::prelude::*;
+use serde::{Deserialize, Serialize};
 
 fn main() {
     let mut app = App::new();
@@ -40,7 +81,24 @@ fn main() {
     .add_plugin(EguiPlugin)
     //.add_plugin(WorldInspectorPlugin::new())
     .add_plugin(UIPlugin)
+    .add_plugin(AlignPlugin)
+    .add_plugin(ClipboardPlugin)
+    .add_plugin(ConnectorPlugin)
     .add_plugin(KeyboardInputPlugin)
+    .add_plugin(DocumentPlugin)
+    .add_plugin(GradientPlugin)
+    .add_plugin(GridPlugin)
+    .add_plugin(GroupPlugin)
+    .add_plugin(HistoryPlugin)
+    .add_plugin(LayersPlugin)
+    .add_plugin(NamingPlugin)
+    .add_plugin(NodeEditorPlugin)
+    .add_plugin(PathOpsPlugin)
+    .add_plugin(SelectionPlugin)
+    .add_plugin(SnappingPlugin)
+    .add_plugin(StrokePlugin)
+    .add_plugin(SvgExportPlugin)
+    .add_plugin(SvgImportPlugin)
     .add_plugin(ShapeTransformPlugin)
     //.add_plugin(DebugEventsPickingPlugin)
     .add_startup_system(spawn_camera)
@@ -62,6 +120,13 @@ fn main() {
             .with_system(custom_shape_handle_update)
             .into(),
     )
+    .add_system_set(
+        ConditionSet::new()
+            .run_if(should_handle_line)
+            .with_system(line_handle_creation)
+            .with_system(line_handle_update)
+            .into(),
+    )
     .add_system_to_stage(PostUpdate, handle_tool_change)
     .add_system_to_stage(PostUpdate, calculate_overlap_order)
     .add_system_to_stage(Last, apply_overlap_order)
@@ -91,6 +156,8 @@ pub struct ChangedOrderEvent {
 #[derive(Default, Resource)]
 pub struct MouseMovement {
     position: Vec2,
+    /// `position` snapped to the grid when snapping is enabled.
+    snapped: Vec2,
     normalized: Vec2,
     over_ui: bool,
 }
@@ -100,25 +167,61 @@ enum ToolType {
     None,
     Primitive(PrimitiveType),
     CustomShape,
+    Line,
+    Connector,
+    EditPoints,
 }
 
 #[derive(Resource)]
 pub struct Tool {
     tool: ToolType,
     color: [u8; 4],
+    corner_radius: f32,
+    markers: Markers,
+    routing: Routing,
 }
 
-#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+#[derive(PartialEq, Copy, Clone, Debug, Serialize, Deserialize)]
 pub enum PrimitiveType {
     Rectangle,
     Ellipse,
     RoundedRectangle,
+    Polygon {
+        sides: u32,
+    },
+    Star {
+        points: u32,
+        /// Radius of the inner points, relative to the outer ones.
+        inner_ratio: f32,
+    },
+}
+
+impl PrimitiveType {
+    pub const ALL: [(PrimitiveType, &'static str); 5] = [
+        (PrimitiveType::Rectangle, "Rectangle"),
+        (PrimitiveType::Ellipse, "Ellipse"),
+        (PrimitiveType::RoundedRectangle, "Rounded Rectangle"),
+        (PrimitiveType::Polygon { sides: 5 }, "Polygon"),
+        (
+            PrimitiveType::Star {
+                points: 5,
+                inner_ratio: 0.5,
+            },
+            "Star",
+        ),
+    ];
 }
 impl Default for Tool {
     fn default() -> Self {
         Self {
             tool: ToolType::None,
             color: [0, 0, 0, 255],
+            corner_radius: 10.0,
+            markers: Markers {
+                start: Marker::None,
+                end: Marker::Arrow,
+            },
+            routing: Routing::Straight,
         }
     }
 }
@@ -132,9 +235,100 @@ impl Default for ToolType {
 pub struct Moving {
     origin: Vec2,
 }
-#[derive(Component)]
-struct PrimitiveShape {
+#[derive(Component, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PrimitiveShape {
     shape: PrimitiveType,
+    extents: Vec2,
+    /// Top-left, top-right, bottom-right and bottom-left, only used by rounded rectangles.
+    corner_radii: [f32; 4],
+}
+
+impl PrimitiveShape {
+    /// Corner radii shrunk the same way lyon does when they don't fit the rectangle.
+    pub fn clamped_corner_radii(&self) -> [f32; 4] {
+        let (w, h) = (self.extents.x, self.extents.y);
+        let [mut tl, mut tr, mut br, mut bl] = self.corner_radii.map(|r| r.abs().min(w.min(h)));
+        if tl + tr > w {
+            let x = (tl + tr - w) * 0.5;
+            tl -= x;
+            tr -= x;
+        }
+        if bl + br > w {
+            let x = (bl + br - w) * 0.5;
+            bl -= x;
+            br -= x;
+        }
+        if tr + br > h {
+            let x = (tr + br - h) * 0.5;
+            tr -= x;
+            br -= x;
+        }
+        if tl + bl > h {
+            let x = (tl + bl - h) * 0.5;
+            tl -= x;
+            bl -= x;
+        }
+        [tl, tr, br, bl]
+    }
+
+    /// Corners of a polygon or star inscribed in the ellipse of the extents, the first one on top.
+    pub fn polygon_points(&self) -> Vec<Vec2> {
+        let radii = self.extents / 2.0;
+        let (count, ratios) = match self.shape {
+            PrimitiveType::Polygon { sides } => (sides.max(3) as usize, [1.0, 1.0]),
+            PrimitiveType::Star {
+                points,
+                inner_ratio,
+            } => (points.max(3) as usize * 2, [1.0, inner_ratio]),
+            _ => return vec![],
+        };
+        (0..count)
+            .map(|i| {
+                let angle = std::f32::consts::FRAC_PI_2
+                    + std::f32::consts::TAU * i as f32 / count as f32;
+                Vec2::new(angle.cos(), angle.sin()) * radii * ratios[i % 2]
+            })
+            .collect()
+    }
+}
+
+impl Geometry for PrimitiveShape {
+    fn add_geometry(&self, b: &mut Builder) {
+        match self.shape {
+            PrimitiveType::Rectangle => shapes::Rectangle {
+                extents: self.extents,
+                origin: RectangleOrigin::Center,
+            }
+            .add_geometry(b),
+            PrimitiveType::Ellipse => shapes::Ellipse {
+                radii: self.extents / 2.0,
+                center: Vec2::ZERO,
+            }
+            .add_geometry(b),
+            PrimitiveType::RoundedRectangle => {
+                let [tl, tr, br, bl] = self.clamped_corner_radii();
+                // lyon names corners for a y-down space, the canvas is y-up.
+                b.add_rounded_rectangle(
+                    &Box2D::new(
+                        Point::new(-self.extents.x / 2.0, -self.extents.y / 2.0),
+                        Point::new(self.extents.x / 2.0, self.extents.y / 2.0),
+                    ),
+                    &BorderRadii {
+                        top_left: bl,
+                        top_right: br,
+                        bottom_left: tl,
+                        bottom_right: tr,
+                    },
+                    Winding::Positive,
+                );
+            }
+            PrimitiveType::Polygon { .. } | PrimitiveType::Star { .. } => shapes::Polygon {
+                points: self.polygon_points(),
+                closed: true,
+            }
+            .add_geometry(b),
+        }
+    }
 }
 
 pub struct ToolChanged;
@@ -157,6 +351,7 @@ fn primitive_handle_creation(
     mouse_input: Res<Input<MouseButton>>,
     mut query: Query<Entity, With<Moving>>,
     mouse: Res<MouseMovement>,
+    mut history: EventWriter<HistoryEvent>,
 ) {
     if mouse_input.just_pressed(MouseButton::Left) && !mouse.over_ui {
         let color = Color::rgba_u8(tool.color[0], tool.color[1], tool.color[2], tool.color[3]);
@@ -164,62 +359,45 @@ fn primitive_handle_creation(
             ToolType::Primitive(t) => t,
             _ => unreachable!(),
         };
-        let shape = match prim_type {
-            PrimitiveType::Rectangle => GeometryBuilder::build_as(
-                &shapes::Rectangle {
-                    extents: Vec2::ZERO,
-                    origin: RectangleOrigin::Center,
-                },
-                DrawMode::Fill(FillMode::color(color)),
-                Transform::from_translation(mouse.position.extend(0.1)),
-            ),
-            PrimitiveType::Ellipse => GeometryBuilder::build_as(
-                &shapes::Ellipse {
-                    radii: Vec2::ZERO,
-                    center: Vec2::ZERO,
-                },
-                DrawMode::Fill(FillMode::color(color)),
-                Transform::from_translation(mouse.position.extend(0.1)),
-            ),
-            _ => unreachable!(),
+        let primitive = PrimitiveShape {
+            shape: prim_type,
+            extents: Vec2::ZERO,
+            corner_radii: [tool.corner_radius; 4],
         };
+        let shape = GeometryBuilder::build_as(
+            &primitive,
+            DrawMode::Fill(FillMode::color(color)),
+            Transform::from_translation(mouse.snapped.extend(0.1)),
+        );
 
         commands
             .spawn(shape)
             .insert((Moving {
-                origin: mouse.position,
+                origin: mouse.snapped,
             },ShapeBase {
                 name: None,
                 originx: Vec3::new(0.0, 0.0, 0.0),
-            },PrimitiveShape { shape: prim_type }));
+            },primitive));
     }
 
     if mouse_input.just_released(MouseButton::Left) {
         if let Ok(id) = query.get_single_mut() {
             commands.entity(id).remove::<Moving>();
             commands.entity(id).insert(PickableBundle::default());
+            history.send(HistoryEvent(EditAction::Created { entity: id }));
         }
     }
 }
 
 fn primitive_handle_update(
     mouse: Res<MouseMovement>,
-    mut query: Query<(&mut Path, &Moving, &mut Transform, &PrimitiveShape)>,
+    mut query: Query<(&mut Path, &Moving, &mut Transform, &mut PrimitiveShape)>,
 ) {
-    if let Ok((mut path, moving, mut transform, prim_type)) = query.get_single_mut() {
-        *path = match prim_type.shape {
-            PrimitiveType::Rectangle => ShapePath::build_as(&shapes::Rectangle {
-                extents: (mouse.position - moving.origin) * Vec2::new(-1.0, -1.0),
-                origin: RectangleOrigin::Center,
-            }),
-            PrimitiveType::Ellipse => ShapePath::build_as(&shapes::Ellipse {
-                radii: (mouse.position - moving.origin) / 2.0,
-                center: Vec2::ZERO,
-            }),
-            _ => unreachable!(),
-        };
+    if let Ok((mut path, moving, mut transform, mut primitive)) = query.get_single_mut() {
+        primitive.extents = (mouse.snapped - moving.origin).abs();
+        *path = ShapePath::build_as(&*primitive);
         *transform = transform.with_translation(
-            (moving.origin + (mouse.position - moving.origin) / 2.0)
+            (moving.origin + (mouse.snapped - moving.origin) / 2.0)
                 .extend(transform.translation.z),
         );
     };
@@ -228,6 +406,7 @@ fn primitive_handle_update(
 fn mouse_position(
     windows: Res<Windows>,
     mut mouse: ResMut<MouseMovement>,
+    grid: Res<Grid>,
     camera: Query<(&Transform, &OrthographicProjection)>,
 ) {
     if let Some(window) = windows.get_primary() {
@@ -238,6 +417,7 @@ fn mouse_position(
             mouse.position = pos.translation.truncate()
                 + mouse_normalized_screen_pos * Vec2::new(cam.right, cam.top) * cam.scale;
             mouse.normalized = mouse_normalized_screen_pos;
+            mouse.snapped = grid.snap(mouse.position);
         }
     }
 }
@@ -282,3 +462,6 @@ fn should_handle_primitive(tool: Res<Tool>) -> bool {
 fn should_handle_custom_shape(tool: Res<Tool>) -> bool {
     tool.tool == ToolType::CustomShape
 }
+fn should_handle_line(tool: Res<Tool>) -> bool {
+    tool.tool == ToolType::Line
+}
@@ -0,0 +1,67 @@
+use crate::custom_shape::CustomShapeRaw;
+use crate::group::ShapeGroup;
+use crate::{PrimitiveShape, PrimitiveType, ShapeBase};
+use bevy::prelude::*;
+
+pub struct NamingPlugin;
+
+impl Plugin for NamingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_to_stage(CoreStage::PostUpdate, name_new_shapes);
+    }
+}
+
+/// Name shown in the UI, the entity id for shapes that haven't been named.
+pub fn display_name(base: &ShapeBase, e: Entity) -> String {
+    match &base.name {
+        Some(name) => name.clone(),
+        None => format!("{:?}", e),
+    }
+}
+
+type NewShape<'a> = (
+    &'a mut ShapeBase,
+    Option<&'a PrimitiveShape>,
+    Option<&'a CustomShapeRaw>,
+    Option<&'a ShapeGroup>,
+);
+
+/// Gives unnamed shapes a name like "Rectangle 3", numbered after the highest existing one.
+fn name_new_shapes(mut shapes: ParamSet<(Query<NewShape, Added<ShapeBase>>, Query<&ShapeBase>)>) {
+    if shapes.p0().iter().all(|(base, ..)| base.name.is_some()) {
+        return;
+    }
+    let mut names = shapes
+        .p1()
+        .iter()
+        .filter_map(|base| base.name.clone())
+        .collect::<Vec<_>>();
+    for (mut base, primitive, custom, group) in shapes.p0().iter_mut() {
+        if base.name.is_some() {
+            continue;
+        }
+        let kind = match (primitive.map(|p| p.shape), custom, group) {
+            (Some(PrimitiveType::Rectangle), ..) => "Rectangle",
+            (Some(PrimitiveType::RoundedRectangle), ..) => "Rounded Rectangle",
+            (Some(PrimitiveType::Ellipse), ..) => "Ellipse",
+            (Some(PrimitiveType::Polygon { .. }), ..) => "Polygon",
+            (Some(PrimitiveType::Star { .. }), ..) => "Star",
+            (_, Some(_), _) => "Path",
+            (_, _, Some(_)) => "Group",
+            _ => "Shape",
+        };
+        let highest = names
+            .iter()
+            .filter_map(|name| {
+                name.strip_prefix(kind)?
+                    .strip_prefix(' ')?
+                    .parse::<u32>()
+                    .ok()
+            })
+            .max()
+            .unwrap_or(0);
+        let name = format!("{} {}", kind, highest + 1);
+        names.push(name.clone());
+        base.name = Some(name);
+    }
+}
@@ -0,0 +1,658 @@
+use crate::connector::Connector;
+use crate::custom_shape::ShapeSegment::*;
+use crate::custom_shape::{CustomShapeRaw, ShapeSegment};
+use crate::document::GeometryRecord;
+use crate::helpers::ctrl_pressed;
+use crate::history::{EditAction, HistoryEvent};
+use crate::{MouseMovement, Moving, ShapeBase, Tool, ToolType};
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+use bevy_mod_picking::Selection;
+use bevy_prototype_lyon::prelude::tess::geom::{
+    CubicBezierSegment, LineSegment, QuadraticBezierSegment,
+};
+use bevy_prototype_lyon::prelude::tess::math::{Point, Vector};
+use bevy_prototype_lyon::prelude::{
+    shapes, DrawMode, GeometryBuilder, Path, RectangleOrigin, ShapePath, StrokeMode,
+};
+use iyes_loopless::prelude::*;
+
+const HANDLE_SIZE: f32 = 8.0;
+const SEGMENT_SAMPLES: usize = 32;
+
+pub struct NodeEditorPlugin;
+
+impl Plugin for NodeEditorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<NodeEditor>()
+            .add_startup_system(spawn_node_overlay)
+            .add_system_set(
+                ConditionSet::new()
+                    .run_if(should_edit_points)
+                    .with_system(edit_nodes)
+                    .with_system(node_editor_window)
+                    .into(),
+            )
+            .add_system(record_node_edits)
+            .add_system(draw_node_handles);
+    }
+}
+
+/// Handles refer to a contour, `0` being the main one and the subpaths following it, and to a
+/// node or segment in that contour.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum NodeHandle {
+    /// Node `0` is the start of the contour, node `n` the end of segment `n - 1`.
+    Node(usize, usize),
+    Ctrl(usize, usize),
+    Ctrl2(usize, usize),
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum SegmentKind {
+    Line,
+    Quadratic,
+    Cubic,
+}
+
+#[derive(Resource, Default)]
+struct NodeEditor {
+    target: Option<Entity>,
+    /// Contour and node.
+    node: Option<(usize, usize)>,
+    dragging: Option<NodeHandle>,
+    before: Option<(Entity, CustomShapeRaw, Transform)>,
+    settle: bool,
+}
+
+impl NodeEditor {
+    fn begin_edit(&mut self, entity: Entity, shape: &CustomShapeRaw, transform: &Transform) {
+        if self.before.is_none() {
+            self.before = Some((entity, shape.clone(), *transform));
+        }
+    }
+
+    /// Undo can shorten the shape under the editor, leaving indices past its end.
+    fn validate(&mut self, shape: &CustomShapeRaw) {
+        if let Some((c, n)) = self.node {
+            if c > shape.subpaths.len() || n > contour(shape, c).1.len() {
+                self.node = None;
+            }
+        }
+        if let Some(handle) = self.dragging {
+            if !handle_positions(shape).iter().any(|(h, _)| *h == handle) {
+                self.dragging = None;
+            }
+        }
+    }
+}
+
+#[derive(Component)]
+struct NodeOverlay;
+
+type EditableShapes<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static mut CustomShapeRaw,
+        &'static mut Path,
+        &'static Transform,
+    ),
+    (Without<Moving>, Without<Connector>),
+>;
+
+fn should_edit_points(tool: Res<Tool>) -> bool {
+    tool.tool == ToolType::EditPoints
+}
+
+fn spawn_node_overlay(mut commands: Commands) {
+    commands
+        .spawn(GeometryBuilder::build_as(
+            &shapes::Rectangle {
+                extents: Vec2::ZERO,
+                origin: RectangleOrigin::Center,
+            },
+            DrawMode::Stroke(StrokeMode::color(Color::ORANGE)),
+            Transform::from_translation(Vec3::new(0.0, 0.0, 150.0)),
+        ))
+        .insert(NodeOverlay);
+}
+
+fn segment_end(segment: &ShapeSegment) -> Point {
+    match *segment {
+        Line(to) | QuadraticBezier { to, .. } | CubicBezier { to, .. } => to,
+    }
+}
+
+fn segment_points_mut(segment: &mut ShapeSegment) -> Vec<&mut Point> {
+    match segment {
+        Line(to) => vec![to],
+        QuadraticBezier { ctrl, to } => vec![ctrl, to],
+        CubicBezier { ctrl, ctrl2, to } => vec![ctrl, ctrl2, to],
+    }
+}
+
+fn segment_kind(segment: &ShapeSegment) -> SegmentKind {
+    match segment {
+        Line(_) => SegmentKind::Line,
+        QuadraticBezier { .. } => SegmentKind::Quadratic,
+        CubicBezier { .. } => SegmentKind::Cubic,
+    }
+}
+
+fn contour(shape: &CustomShapeRaw, c: usize) -> (Point, &[ShapeSegment], bool) {
+    shape
+        .contours()
+        .nth(c)
+        .unwrap_or((Point::zero(), &[], false))
+}
+
+fn contour_segments_mut(shape: &mut CustomShapeRaw, c: usize) -> &mut Vec<ShapeSegment> {
+    match c {
+        0 => &mut shape.segments,
+        c => &mut shape.subpaths[c - 1].segments,
+    }
+}
+
+/// Node positions are relative to the start of the shape, like the segments themselves.
+fn node_position(shape: &CustomShapeRaw, c: usize, node: usize) -> Point {
+    let (start, segments, _) = contour(shape, c);
+    match node {
+        0 => start,
+        n => segment_end(&segments[n - 1]),
+    }
+}
+
+/// Segment `i` of a contour, including the implicit line closing it.
+fn outline_segment(shape: &CustomShapeRaw, c: usize, i: usize) -> ShapeSegment {
+    let (start, segments, _) = contour(shape, c);
+    segments.get(i).cloned().unwrap_or(Line(start))
+}
+
+fn outline_len(shape: &CustomShapeRaw, c: usize) -> usize {
+    let (_, segments, closed) = contour(shape, c);
+    segments.len() + closed as usize
+}
+
+fn handle_positions(shape: &CustomShapeRaw) -> Vec<(NodeHandle, Point)> {
+    let mut handles = vec![];
+    for (c, (start, segments, _)) in shape.contours().enumerate() {
+        handles.push((NodeHandle::Node(c, 0), start));
+        for (i, segment) in segments.iter().enumerate() {
+            handles.push((NodeHandle::Node(c, i + 1), segment_end(segment)));
+        }
+    }
+    // Nodes come first, so they win over control points lying on top of them.
+    for (c, (_, segments, _)) in shape.contours().enumerate() {
+        for (i, segment) in segments.iter().enumerate() {
+            match *segment {
+                Line(_) => {}
+                QuadraticBezier { ctrl, .. } => handles.push((NodeHandle::Ctrl(c, i), ctrl)),
+                CubicBezier { ctrl, ctrl2, .. } => {
+                    handles.push((NodeHandle::Ctrl(c, i), ctrl));
+                    handles.push((NodeHandle::Ctrl2(c, i), ctrl2));
+                }
+            }
+        }
+    }
+    handles
+}
+
+fn sample_segment(from: Point, segment: &ShapeSegment, t: f32) -> Point {
+    match *segment {
+        Line(to) => LineSegment { from, to }.sample(t),
+        QuadraticBezier { ctrl, to } => QuadraticBezierSegment { from, ctrl, to }.sample(t),
+        CubicBezier { ctrl, ctrl2, to } => CubicBezierSegment {
+            from,
+            ctrl1: ctrl,
+            ctrl2,
+            to,
+        }
+        .sample(t),
+    }
+}
+
+fn split_segment(from: Point, segment: &ShapeSegment, t: f32) -> (ShapeSegment, ShapeSegment) {
+    match *segment {
+        Line(to) => (Line(from.lerp(to, t)), Line(to)),
+        QuadraticBezier { ctrl, to } => {
+            let (a, b) = QuadraticBezierSegment { from, ctrl, to }.split(t);
+            (
+                QuadraticBezier {
+                    ctrl: a.ctrl,
+                    to: a.to,
+                },
+                QuadraticBezier {
+                    ctrl: b.ctrl,
+                    to: b.to,
+                },
+            )
+        }
+        CubicBezier { ctrl, ctrl2, to } => {
+            let (a, b) = CubicBezierSegment {
+                from,
+                ctrl1: ctrl,
+                ctrl2,
+                to,
+            }
+            .split(t);
+            (
+                CubicBezier {
+                    ctrl: a.ctrl1,
+                    ctrl2: a.ctrl2,
+                    to: a.to,
+                },
+                CubicBezier {
+                    ctrl: b.ctrl1,
+                    ctrl2: b.ctrl2,
+                    to: b.to,
+                },
+            )
+        }
+    }
+}
+
+/// Moves the start of the shape by `offset` while every other node stays where it is.
+fn shift_start(shape: &mut CustomShapeRaw, offset: Vector) {
+    shape.origin += Vec2::new(offset.x, offset.y);
+    for segment in shape.segments.iter_mut() {
+        for p in segment_points_mut(segment) {
+            *p -= offset;
+        }
+    }
+    for subpath in shape.subpaths.iter_mut() {
+        subpath.start -= offset;
+        for segment in subpath.segments.iter_mut() {
+            for p in segment_points_mut(segment) {
+                *p -= offset;
+            }
+        }
+    }
+}
+
+fn move_handle(shape: &mut CustomShapeRaw, handle: NodeHandle, to: Point) {
+    match handle {
+        NodeHandle::Node(0, 0) => shift_start(shape, to.to_vector()),
+        NodeHandle::Node(c, 0) => {
+            let subpath = &mut shape.subpaths[c - 1];
+            let offset = to - subpath.start;
+            subpath.start = to;
+            if let Some(CubicBezier { ctrl, .. }) = subpath.segments.get_mut(0) {
+                *ctrl += offset;
+            }
+        }
+        NodeHandle::Node(c, n) => {
+            let segments = contour_segments_mut(shape, c);
+            // Cubic control points attached to the node follow it.
+            let offset = to - segment_end(&segments[n - 1]);
+            if let CubicBezier { ctrl2, .. } = &mut segments[n - 1] {
+                *ctrl2 += offset;
+            }
+            if let Some(CubicBezier { ctrl, .. }) = segments.get_mut(n) {
+                *ctrl += offset;
+            }
+            if let Some(end) = segment_points_mut(&mut segments[n - 1]).pop() {
+                *end = to;
+            }
+        }
+        NodeHandle::Ctrl(c, i) => match &mut contour_segments_mut(shape, c)[i] {
+            QuadraticBezier { ctrl, .. } | CubicBezier { ctrl, .. } => *ctrl = to,
+            Line(_) => {}
+        },
+        NodeHandle::Ctrl2(c, i) => {
+            if let CubicBezier { ctrl2, .. } = &mut contour_segments_mut(shape, c)[i] {
+                *ctrl2 = to;
+            }
+        }
+    }
+}
+
+fn delete_node(shape: &mut CustomShapeRaw, c: usize, node: usize) -> bool {
+    if contour(shape, c).1.len() < 2 {
+        return false;
+    }
+    match (c, node) {
+        (0, 0) => {
+            let offset = segment_end(&shape.segments[0]).to_vector();
+            shape.segments.remove(0);
+            shift_start(shape, offset);
+        }
+        (c, 0) => {
+            let subpath = &mut shape.subpaths[c - 1];
+            subpath.start = segment_end(&subpath.segments.remove(0));
+        }
+        (c, n) => {
+            // The following segment now starts at the previous node.
+            contour_segments_mut(shape, c).remove(n - 1);
+        }
+    }
+    true
+}
+
+fn convert_segment(shape: &mut CustomShapeRaw, c: usize, i: usize, kind: SegmentKind) {
+    let from = node_position(shape, c, i);
+    let segment = &contour(shape, c).1[i];
+    let to = segment_end(segment);
+    let converted = match (kind, segment) {
+        (SegmentKind::Line, _) => Line(to),
+        (SegmentKind::Quadratic, Line(_)) => QuadraticBezier {
+            ctrl: from.lerp(to, 0.5),
+            to,
+        },
+        (SegmentKind::Quadratic, QuadraticBezier { ctrl, .. }) => {
+            QuadraticBezier { ctrl: *ctrl, to }
+        }
+        (SegmentKind::Quadratic, CubicBezier { ctrl, ctrl2, .. }) => QuadraticBezier {
+            ctrl: ((ctrl.to_vector() + ctrl2.to_vector()) * 0.75
+                - (from.to_vector() + to.to_vector()) * 0.25)
+                .to_point(),
+            to,
+        },
+        (SegmentKind::Cubic, Line(_)) => CubicBezier {
+            ctrl: from.lerp(to, 1.0 / 3.0),
+            ctrl2: from.lerp(to, 2.0 / 3.0),
+            to,
+        },
+        (SegmentKind::Cubic, QuadraticBezier { ctrl, .. }) => CubicBezier {
+            ctrl: from.lerp(*ctrl, 2.0 / 3.0),
+            ctrl2: to.lerp(*ctrl, 2.0 / 3.0),
+            to,
+        },
+        (SegmentKind::Cubic, CubicBezier { .. }) => segment.clone(),
+    };
+    contour_segments_mut(shape, c)[i] = converted;
+}
+
+fn to_world(shape: &CustomShapeRaw, transform: &Transform, p: Point) -> Vec2 {
+    transform
+        .transform_point((Vec2::new(p.x, p.y) + shape.origin).extend(0.0))
+        .truncate()
+}
+
+fn to_shape(shape: &CustomShapeRaw, transform: &Transform, position: Vec2) -> Point {
+    let local = transform
+        .compute_matrix()
+        .inverse()
+        .transform_point3(position.extend(0.0))
+        .truncate()
+        - shape.origin;
+    Point::new(local.x, local.y)
+}
+
+fn select_only(target: Entity, selection: &mut Query<(Entity, &mut Selection), With<ShapeBase>>) {
+    for (e, mut s) in selection.iter_mut() {
+        s.set_selected(e == target);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn edit_nodes(
+    mut editor: ResMut<NodeEditor>,
+    input: Res<Input<KeyCode>>,
+    mouse_input: Res<Input<MouseButton>>,
+    mouse: Res<MouseMovement>,
+    projection: Query<&OrthographicProjection>,
+    mut selection: Query<(Entity, &mut Selection), With<ShapeBase>>,
+    mut shapes: EditableShapes,
+) {
+    let tolerance = projection.get_single().map_or(1.0, |p| p.scale) * HANDLE_SIZE;
+    if let Some(target) = editor.target {
+        if let Ok((shape, _, _)) = shapes.get(target) {
+            editor.validate(shape);
+        }
+    }
+    if mouse_input.just_pressed(MouseButton::Left) && !mouse.over_ui {
+        if let Some(target) = editor.target {
+            if let Ok((mut shape, mut path, transform)) = shapes.get_mut(target) {
+                let hit = handle_positions(&shape)
+                    .into_iter()
+                    .map(|(handle, p)| {
+                        let distance = to_world(&shape, transform, p).distance(mouse.position);
+                        (handle, distance)
+                    })
+                    .filter(|(_, distance)| *distance <= tolerance)
+                    .min_by(|a, b| a.1.total_cmp(&b.1));
+                if let Some((handle, _)) = hit {
+                    editor.node = Some(match handle {
+                        NodeHandle::Node(c, n) => (c, n),
+                        NodeHandle::Ctrl(c, i) | NodeHandle::Ctrl2(c, i) => (c, i + 1),
+                    });
+                    editor.dragging = Some(handle);
+                    editor.begin_edit(target, &shape, transform);
+                    select_only(target, &mut selection);
+                } else if ctrl_pressed(&input) {
+                    let nearest = (0..=shape.subpaths.len())
+                        .flat_map(|c| (0..outline_len(&shape, c)).map(move |i| (c, i)))
+                        .flat_map(|(c, i)| (0..=SEGMENT_SAMPLES).map(move |j| (c, i, j)))
+                        .map(|(c, i, j)| {
+                            let t = j as f32 / SEGMENT_SAMPLES as f32;
+                            let p = sample_segment(
+                                node_position(&shape, c, i),
+                                &outline_segment(&shape, c, i),
+                                t,
+                            );
+                            let distance = to_world(&shape, transform, p).distance(mouse.position);
+                            (c, i, t, distance)
+                        })
+                        .min_by(|a, b| a.3.total_cmp(&b.3));
+                    if let Some((c, i, t, distance)) = nearest {
+                        if distance <= tolerance {
+                            editor.begin_edit(target, &shape, transform);
+                            let from = node_position(&shape, c, i);
+                            let (a, b) = split_segment(from, &outline_segment(&shape, c, i), t);
+                            let segments = contour_segments_mut(&mut shape, c);
+                            if i < segments.len() {
+                                segments[i] = a;
+                                segments.insert(i + 1, b);
+                            } else {
+                                segments.push(a);
+                            }
+                            *path = ShapePath::build_as(&*shape);
+                            editor.node = Some((c, i + 1));
+                            editor.settle = true;
+                            select_only(target, &mut selection);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    if let Some(handle) = editor.dragging {
+        if let Some(target) = editor.target {
+            if mouse_input.pressed(MouseButton::Left) {
+                if let Ok((mut shape, mut path, transform)) = shapes.get_mut(target) {
+                    let to = to_shape(&shape, transform, mouse.snapped);
+                    move_handle(&mut shape, handle, to);
+                    *path = ShapePath::build_as(&*shape);
+                }
+            }
+        }
+        if !mouse_input.pressed(MouseButton::Left) {
+            editor.dragging = None;
+            editor.settle = true;
+        }
+        return;
+    }
+    if input.just_pressed(KeyCode::Delete) {
+        if let (Some(target), Some((c, node))) = (editor.target, editor.node) {
+            if let Ok((mut shape, mut path, transform)) = shapes.get_mut(target) {
+                let mut edited = shape.clone();
+                if delete_node(&mut edited, c, node) {
+                    editor.begin_edit(target, &shape, transform);
+                    *shape = edited;
+                    *path = ShapePath::build_as(&*shape);
+                    editor.node = None;
+                    editor.settle = true;
+                }
+            }
+        }
+    }
+    let target = selection
+        .iter()
+        .find(|(e, s)| s.selected() && shapes.contains(*e))
+        .map(|(e, _)| e);
+    if target != editor.target {
+        editor.target = target;
+        editor.node = None;
+    }
+}
+
+fn node_editor_window(
+    mut egui_context: ResMut<EguiContext>,
+    mut editor: ResMut<NodeEditor>,
+    mut mouse: ResMut<MouseMovement>,
+    mut shapes: EditableShapes,
+    connectors: Query<&Selection, With<Connector>>,
+) {
+    let target = match editor.target {
+        Some(target) => target,
+        None => {
+            // Connectors are routed again as soon as their points change.
+            if connectors.iter().any(|s| s.selected()) {
+                egui::Window::new("Edit Points").show(egui_context.ctx_mut(), |ui| {
+                    ui.label("Connectors follow the shapes they are attached to.");
+                    ui.label("Their points can't be edited.");
+                });
+                mouse.over_ui = egui_context.ctx_mut().wants_pointer_input();
+            }
+            return;
+        }
+    };
+    let (mut shape, mut path, transform) = match shapes.get_mut(target) {
+        Ok(shape) => shape,
+        Err(_) => return,
+    };
+    editor.validate(&shape);
+    let mut edited = shape.clone();
+    let mut node = editor.node;
+    egui::Window::new("Edit Points").show(egui_context.ctx_mut(), |ui| {
+        ui.label("Drag nodes and control points, Ctrl+click a segment to insert a node.");
+        match node {
+            Some((c, n)) => {
+                match c {
+                    0 => ui.label(format!("Node {}", n)),
+                    c => ui.label(format!("Node {} of subpath {}", n, c)),
+                };
+                let segments = contour(&edited, c).1;
+                if let Some(segment) = n.checked_sub(1).and_then(|i| segments.get(i)) {
+                    let kind = segment_kind(segment);
+                    ui.horizontal(|ui| {
+                        for (k, label) in [
+                            (SegmentKind::Line, "Line"),
+                            (SegmentKind::Quadratic, "Quadratic"),
+                            (SegmentKind::Cubic, "Cubic"),
+                        ] {
+                            if ui.selectable_label(kind == k, label).clicked() && kind != k {
+                                convert_segment(&mut edited, c, n - 1, k);
+                            }
+                        }
+                    });
+                }
+                if ui.button("Delete node").clicked() && delete_node(&mut edited, c, n) {
+                    node = None;
+                }
+            }
+            None => {
+                ui.label("Click a node to select it.");
+            }
+        }
+    });
+    editor.node = node;
+    if edited != *shape {
+        editor.begin_edit(target, &shape, transform);
+        *shape = edited;
+        *path = ShapePath::build_as(&*shape);
+        editor.settle = true;
+    }
+    mouse.over_ui = egui_context.ctx_mut().wants_pointer_input();
+}
+
+/// Edits are recorded one frame late, once `update_origin` has re-centered the shape,
+/// so that undo restores the matching translation as well.
+fn record_node_edits(
+    mut editor: ResMut<NodeEditor>,
+    shapes: Query<(&CustomShapeRaw, &Transform)>,
+    mut history: EventWriter<HistoryEvent>,
+    mut settled: Local<bool>,
+) {
+    if !*settled {
+        *settled = editor.settle;
+        return;
+    }
+    *settled = false;
+    editor.settle = false;
+    if let Some((entity, shape_before, transform_before)) = editor.before.take() {
+        if let Ok((shape, transform)) = shapes.get(entity) {
+            if *shape != shape_before {
+                history.send(HistoryEvent(EditAction::Reshaped {
+                    entity,
+                    before: GeometryRecord::Custom(shape_before),
+                    after: GeometryRecord::Custom(shape.clone()),
+                }));
+                if *transform != transform_before {
+                    history.send(HistoryEvent(EditAction::Transformed {
+                        entity,
+                        before: transform_before,
+                        after: *transform,
+                    }));
+                }
+            }
+        }
+    }
+}
+
+fn draw_node_handles(
+    tool: Res<Tool>,
+    editor: Res<NodeEditor>,
+    projection: Query<&OrthographicProjection>,
+    shapes: Query<(&CustomShapeRaw, &Transform)>,
+    mut overlay: Query<(&mut Path, &mut DrawMode), With<NodeOverlay>>,
+) {
+    let (mut path, mut draw_mode) = match overlay.get_single_mut() {
+        Ok(overlay) => overlay,
+        Err(_) => return,
+    };
+    let target = editor.target.and_then(|e| shapes.get(e).ok());
+    let (shape, transform) = match target {
+        Some(target) if tool.tool == ToolType::EditPoints => target,
+        _ => {
+            *path = ShapePath::build_as(&shapes::Rectangle {
+                extents: Vec2::ZERO,
+                origin: RectangleOrigin::Center,
+            });
+            return;
+        }
+    };
+    let scale = projection.get_single().map_or(1.0, |p| p.scale);
+    let world = |p: Point| to_world(shape, transform, p);
+    let mut builder = ShapePath::new();
+    for (c, (_, segments, _)) in shape.contours().enumerate() {
+        for (i, segment) in segments.iter().enumerate() {
+            let from = world(node_position(shape, c, i));
+            let to = world(segment_end(segment));
+            let controls = match *segment {
+                Line(_) => vec![],
+                QuadraticBezier { ctrl, .. } => vec![(from, world(ctrl)), (to, world(ctrl))],
+                CubicBezier { ctrl, ctrl2, .. } => {
+                    vec![(from, world(ctrl)), (to, world(ctrl2))]
+                }
+            };
+            for (node, ctrl) in controls {
+                builder = builder.add(&shapes::Line(node, ctrl)).add(&shapes::Circle {
+                    radius: HANDLE_SIZE / 2.0 * scale,
+                    center: ctrl,
+                });
+            }
+        }
+        for n in 0..=segments.len() {
+            let selected = editor.node == Some((c, n));
+            let size = if selected { 1.5 } else { 1.0 } * HANDLE_SIZE * scale;
+            builder = builder.add(&shapes::Rectangle {
+                extents: Vec2::splat(size),
+                origin: RectangleOrigin::CustomCenter(world(node_position(shape, c, n))),
+            });
+        }
+    }
+    *path = builder.build();
+    *draw_mode = DrawMode::Stroke(StrokeMode::new(Color::ORANGE, scale * 1.5));
+}
@@ -1,8 +1,11 @@
+use crate::group::ShapeGroup;
+use crate::history::{EditAction, HistoryEvent};
 use crate::{ChangedOrderEvent, OrderedShapes, ShapeBase};
 use bevy::prelude::*;
 
 pub fn apply_overlap_order(
     mut objects: Query<&mut Transform, With<ShapeBase>>,
+    groups: Query<&Children, With<ShapeGroup>>,
     ordered: Res<OrderedShapes>,
 ) {
     for (index, e) in ordered.0.iter().enumerate() {
@@ -14,28 +17,60 @@ pub fn apply_overlap_order(
             entity_transform.translation.y,
             index as f32,
         ));
+        apply_group_order(*e, 1.0, &mut objects, &groups);
+    }
+}
+
+/// Stacks the children of a group inside the depth range the group itself occupies.
+fn apply_group_order(
+    group: Entity,
+    range: f32,
+    objects: &mut Query<&mut Transform, With<ShapeBase>>,
+    groups: &Query<&Children, With<ShapeGroup>>,
+) {
+    if let Ok(children) = groups.get(group) {
+        let step = range / children.len() as f32;
+        for (index, child) in children.iter().enumerate() {
+            if let Ok(mut transform) = objects.get_mut(*child) {
+                transform.translation.z = index as f32 * step;
+            }
+            apply_group_order(*child, step, objects, groups);
+        }
     }
 }
 
 pub fn calculate_overlap_order(
-    new_objects: Query<Entity, Added<ShapeBase>>,
+    new_objects: Query<Entity, (Added<ShapeBase>, Without<Parent>)>,
     mut changed_objects: EventReader<ChangedOrderEvent>,
     mut ordered_objects: ResMut<OrderedShapes>,
+    mut history: EventWriter<HistoryEvent>,
 ) {
     for e in new_objects.iter() {
-        ordered_objects.0.push(e);
+        if !ordered_objects.0.contains(&e) {
+            ordered_objects.0.push(e);
+        }
     }
     for (e, top, removed) in changed_objects
         .iter()
         .map(|c| (c.entity, c.change_up, c.removed))
     {
-        let i = ordered_objects.0.iter().position(|ent| ent == &e).unwrap();
+        // Shapes can leave the order before their event arrives, like one grouped and deleted
+        // in the same frame.
+        let i = match ordered_objects.0.iter().position(|ent| ent == &e) {
+            Some(i) => i,
+            None => continue,
+        };
 
         if removed {
             ordered_objects.0.remove(i);
         } else if (i + top as usize) < ordered_objects.0.len() && (i as isize - !top as isize) >= 0
         {
+            let before = ordered_objects.0.clone();
             ordered_objects.0.swap(i - !top as usize, i + top as usize);
+            history.send(HistoryEvent(EditAction::Reordered {
+                before,
+                after: ordered_objects.0.clone(),
+            }));
         }
     }
 }
@@ -0,0 +1,620 @@
+use crate::custom_shape::{CustomShapeRaw, ShapeSegment, Subpath};
+use crate::document::{apply_geometry, spawn_shape, GeometryRecord, ShapeRecord, ShapeRecordQuery};
+use crate::helpers::{ctrl_pressed, point_from_positions, shift_pressed};
+use crate::history::{EditAction, HistoryEvent};
+use crate::keyboard_input::delete_shapes;
+use crate::layers::Locked;
+use crate::{
+    ChangedOrderEvent, MouseMovement, OrderedShapes, PrimitiveShape, PrimitiveType, ShapeBase,
+};
+use bevy::prelude::*;
+use bevy_egui::EguiContext;
+use bevy_mod_picking::Selection;
+use bevy_prototype_lyon::prelude::tess::math::Point;
+use bevy_prototype_lyon::prelude::tess::path::iterator::PathIterator;
+use bevy_prototype_lyon::prelude::tess::path::PathEvent;
+use bevy_prototype_lyon::prelude::{DrawMode, FillRule, Path};
+use geo::{BooleanOps, LineString, MultiPolygon, Polygon};
+
+/// Maximum distance between a curve and the polygon it is flattened into.
+const FLATTEN_TOLERANCE: f32 = 0.1;
+
+pub struct PathOpsPlugin;
+
+impl Plugin for PathOpsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<PathOpEvent>()
+            .add_system(handle_path_op_input)
+            .add_system(apply_path_ops.after(handle_path_op_input).after("egui"));
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PathOperation {
+    Combine,
+    Union,
+    Difference,
+    Intersection,
+    Exclusion,
+    ToPath,
+}
+
+impl PathOperation {
+    pub const ALL: [(PathOperation, &'static str); 6] = [
+        (PathOperation::ToPath, "Object to Path"),
+        (PathOperation::Combine, "Combine"),
+        (PathOperation::Union, "Union"),
+        (PathOperation::Difference, "Difference"),
+        (PathOperation::Intersection, "Intersection"),
+        (PathOperation::Exclusion, "Exclusion"),
+    ];
+}
+
+pub struct PathOpEvent(pub PathOperation);
+
+/// Ctrl + K combines the selected paths, Ctrl + Plus, Minus, Asterisk and Caret apply
+/// boolean operations and Ctrl + Shift + C converts primitives to paths.
+fn handle_path_op_input(
+    mut egui_context: ResMut<EguiContext>,
+    input: Res<Input<KeyCode>>,
+    mouse: Res<MouseMovement>,
+    mut events: EventWriter<PathOpEvent>,
+) {
+    if !ctrl_pressed(&input) || mouse.over_ui || egui_context.ctx_mut().wants_keyboard_input() {
+        return;
+    }
+    let keys = [
+        (KeyCode::K, PathOperation::Combine),
+        (KeyCode::Plus, PathOperation::Union),
+        (KeyCode::Equals, PathOperation::Union),
+        (KeyCode::NumpadAdd, PathOperation::Union),
+        (KeyCode::Minus, PathOperation::Difference),
+        (KeyCode::NumpadSubtract, PathOperation::Difference),
+        (KeyCode::Asterisk, PathOperation::Intersection),
+        (KeyCode::NumpadMultiply, PathOperation::Intersection),
+        (KeyCode::Caret, PathOperation::Exclusion),
+    ];
+    if shift_pressed(&input) && input.just_pressed(KeyCode::C) {
+        events.send(PathOpEvent(PathOperation::ToPath));
+    }
+    for (key, operation) in keys {
+        if input.just_pressed(key) {
+            events.send(PathOpEvent(operation));
+        }
+    }
+}
+
+/// Moves the contours of `others` into the space of `target`, as subpaths after its own.
+fn combine_paths(
+    target: (&CustomShapeRaw, &Transform),
+    others: &[(&CustomShapeRaw, &Transform)],
+) -> CustomShapeRaw {
+    let (target, target_transform) = target;
+    let inverse = target_transform.compute_matrix().inverse();
+    let mut combined = target.clone();
+    for (shape, transform) in others {
+        let to_target = inverse * transform.compute_matrix();
+        let map = |p: Point| {
+            let local = to_target
+                .transform_point3((Vec2::new(p.x, p.y) + shape.origin).extend(0.0))
+                .truncate()
+                - target.origin;
+            Point::new(local.x, local.y)
+        };
+        for (start, segments, closed) in shape.contours() {
+            combined.subpaths.push(Subpath {
+                start: map(start),
+                segments: segments.iter().map(|s| s.map(map)).collect(),
+                closed,
+            });
+        }
+    }
+    combined
+}
+
+/// Distance of the control points of a cubic Bezier approximating a quarter circle of radius 1.
+const KAPPA: f32 = 0.552_284_8;
+
+/// The same outline as a primitive, as segments that the node editor can work on.
+fn primitive_path(primitive: &PrimitiveShape) -> CustomShapeRaw {
+    let (hx, hy) = (primitive.extents.x / 2.0, primitive.extents.y / 2.0);
+    let p = Point::new;
+    let (start, segments) = match primitive.shape {
+        PrimitiveType::Rectangle => (
+            p(-hx, -hy),
+            vec![
+                ShapeSegment::Line(p(hx, -hy)),
+                ShapeSegment::Line(p(hx, hy)),
+                ShapeSegment::Line(p(-hx, hy)),
+            ],
+        ),
+        PrimitiveType::Ellipse => {
+            let (kx, ky) = (hx * KAPPA, hy * KAPPA);
+            let quarter = |ctrl, ctrl2, to| ShapeSegment::CubicBezier { ctrl, ctrl2, to };
+            (
+                p(hx, 0.0),
+                vec![
+                    quarter(p(hx, ky), p(kx, hy), p(0.0, hy)),
+                    quarter(p(-kx, hy), p(-hx, ky), p(-hx, 0.0)),
+                    quarter(p(-hx, -ky), p(-kx, -hy), p(0.0, -hy)),
+                    quarter(p(kx, -hy), p(hx, -ky), p(hx, 0.0)),
+                ],
+            )
+        }
+        PrimitiveType::RoundedRectangle => {
+            let [tl, tr, br, bl] = primitive.clamped_corner_radii();
+            // Counterclockwise from the bottom edge, the corners without a radius are skipped.
+            let corner = |radius: f32, ctrl, ctrl2, to| {
+                (radius > 0.0).then_some(ShapeSegment::CubicBezier { ctrl, ctrl2, to })
+            };
+            let k = 1.0 - KAPPA;
+            let mut edges = vec![ShapeSegment::Line(p(hx - br, -hy))];
+            edges.extend(corner(
+                br,
+                p(hx - br * k, -hy),
+                p(hx, -hy + br * k),
+                p(hx, -hy + br),
+            ));
+            edges.push(ShapeSegment::Line(p(hx, hy - tr)));
+            edges.extend(corner(
+                tr,
+                p(hx, hy - tr * k),
+                p(hx - tr * k, hy),
+                p(hx - tr, hy),
+            ));
+            edges.push(ShapeSegment::Line(p(-hx + tl, hy)));
+            edges.extend(corner(
+                tl,
+                p(-hx + tl * k, hy),
+                p(-hx, hy - tl * k),
+                p(-hx, hy - tl),
+            ));
+            edges.push(ShapeSegment::Line(p(-hx, -hy + bl)));
+            edges.extend(corner(
+                bl,
+                p(-hx, -hy + bl * k),
+                p(-hx + bl * k, -hy),
+                p(-hx + bl, -hy),
+            ));
+            (p(-hx + bl, -hy), edges)
+        }
+        PrimitiveType::Polygon { .. } | PrimitiveType::Star { .. } => {
+            let points = primitive.polygon_points();
+            let segments = points[1..]
+                .iter()
+                .map(|v| ShapeSegment::Line(p(v.x, v.y)))
+                .collect();
+            (p(points[0].x, points[0].y), segments)
+        }
+    };
+    let origin = Vec2::new(start.x, start.y);
+    let mut segments = segments
+        .iter()
+        .map(|s| s.map(|to| p(to.x - start.x, to.y - start.y)))
+        .collect::<Vec<_>>();
+    // The outline is closed, a last line back to the start would only repeat it.
+    if segments.last() == Some(&ShapeSegment::Line(Point::zero())) {
+        segments.pop();
+    }
+    CustomShapeRaw {
+        segments,
+        closed: true,
+        origin,
+        subpaths: vec![],
+    }
+}
+
+fn signed_area(ring: &[(f64, f64)]) -> f64 {
+    let edges = ring.iter().zip(ring.iter().cycle().skip(1));
+    edges.map(|(a, b)| a.0 * b.1 - b.0 * a.1).sum::<f64>() / 2.0
+}
+
+fn ring_polygon(ring: Vec<(f64, f64)>) -> MultiPolygon {
+    MultiPolygon(vec![Polygon::new(LineString::from(ring), vec![])])
+}
+
+fn union_all(polygons: impl Iterator<Item = MultiPolygon>) -> MultiPolygon {
+    polygons.fold(MultiPolygon(vec![]), |a, b| a.union(&b))
+}
+
+/// Flattens the outline of a shape into world space polygons. Open contours are filled as if
+/// closed, like lyon does. With the non-zero rule, contours wound against the largest one are
+/// treated as holes.
+fn shape_polygons(path: &Path, transform: &Transform, rule: FillRule) -> MultiPolygon {
+    let matrix = transform.compute_matrix();
+    let world = |p: Point| {
+        let p = matrix.transform_point3(Vec3::new(p.x, p.y, 0.0));
+        (p.x as f64, p.y as f64)
+    };
+    let mut rings = vec![];
+    let mut ring = vec![];
+    for event in path.0.iter().flattened(FLATTEN_TOLERANCE) {
+        match event {
+            PathEvent::Begin { at } => ring = vec![world(at)],
+            PathEvent::Line { to, .. } => ring.push(world(to)),
+            PathEvent::End { .. } => rings.push(std::mem::take(&mut ring)),
+            _ => {}
+        }
+    }
+    rings.retain(|r| r.len() > 2);
+    match rule {
+        FillRule::EvenOdd => rings
+            .into_iter()
+            .map(ring_polygon)
+            .fold(MultiPolygon(vec![]), |a, b| a.xor(&b)),
+        FillRule::NonZero => {
+            let outer = rings
+                .iter()
+                .map(|r| signed_area(r))
+                .max_by(|a, b| a.abs().total_cmp(&b.abs()))
+                .unwrap_or(0.0);
+            let (filled, holes): (Vec<_>, Vec<_>) = rings
+                .into_iter()
+                .partition(|r| signed_area(r).signum() == outer.signum());
+            union_all(filled.into_iter().map(ring_polygon))
+                .difference(&union_all(holes.into_iter().map(ring_polygon)))
+        }
+    }
+}
+
+/// Turns the rings of `polygons` into a closed path in the space of `transform`, centered on
+/// its bounds like the shapes drawn with the pen. Also returns the center.
+fn polygon_path(polygons: &MultiPolygon, transform: &Transform) -> Option<(CustomShapeRaw, Vec2)> {
+    let inverse = transform.compute_matrix().inverse();
+    let rings = polygons
+        .iter()
+        .flat_map(|p| std::iter::once(p.exterior()).chain(p.interiors()))
+        .map(|ring| {
+            let mut points = ring
+                .coords()
+                .map(|c| {
+                    inverse
+                        .transform_point3(Vec3::new(c.x as f32, c.y as f32, 0.0))
+                        .truncate()
+                })
+                .collect::<Vec<_>>();
+            // Rings repeat their first point at the end.
+            points.pop();
+            points
+        })
+        .filter(|points| points.len() > 2)
+        .collect::<Vec<_>>();
+    let (min, max) = rings.iter().flatten().fold(
+        (Vec2::splat(f32::MAX), Vec2::splat(f32::MIN)),
+        |(min, max), p| (min.min(*p), max.max(*p)),
+    );
+    let center = (min + max) / 2.0;
+    let mut rings = rings.into_iter();
+    let first = rings.next()?;
+    let origin = first[0];
+    let segments = |points: &[Vec2]| {
+        points
+            .iter()
+            .map(|p| ShapeSegment::Line(point_from_positions(*p, origin)))
+            .collect::<Vec<_>>()
+    };
+    let shape = CustomShapeRaw {
+        segments: segments(&first[1..]),
+        closed: true,
+        origin: origin - center,
+        subpaths: rings
+            .map(|ring| Subpath {
+                start: point_from_positions(ring[0], origin),
+                segments: segments(&ring[1..]),
+                closed: true,
+            })
+            .collect(),
+    };
+    Some((shape, center))
+}
+
+/// Computes the boolean operation on the outlines of `shapes`, bottom to top.
+fn boolean_result(operation: PathOperation, mut shapes: Vec<MultiPolygon>) -> MultiPolygon {
+    if shapes.is_empty() {
+        return MultiPolygon(vec![]);
+    }
+    let first = shapes.remove(0);
+    let rest = shapes.into_iter();
+    match operation {
+        PathOperation::Union | PathOperation::Combine | PathOperation::ToPath => {
+            rest.fold(first, |a, b| a.union(&b))
+        }
+        PathOperation::Intersection => rest.fold(first, |a, b| a.intersection(&b)),
+        PathOperation::Exclusion => rest.fold(first, |a, b| a.xor(&b)),
+        // The shapes above are cut out of the bottom one.
+        PathOperation::Difference => first.difference(&union_all(rest)),
+    }
+}
+
+/// Replaces `shapes` with the result of a boolean operation, which keeps the style, transform
+/// and place in the stack of the bottom one. Returns the new shape.
+#[allow(clippy::too_many_arguments)]
+fn apply_boolean(
+    commands: &mut Commands,
+    operation: PathOperation,
+    shapes: &[Entity],
+    ordered: &mut OrderedShapes,
+    outlines: &Query<(&Path, &Transform, Option<&DrawMode>), Without<Locked>>,
+    records: &Query<ShapeRecordQuery>,
+    history: &mut EventWriter<HistoryEvent>,
+    changed: &mut EventWriter<ChangedOrderEvent>,
+) -> Option<Entity> {
+    let polygons = shapes
+        .iter()
+        .filter_map(|e| {
+            let (path, transform, draw_mode) = outlines.get(*e).ok()?;
+            let rule = match draw_mode {
+                Some(DrawMode::Fill(fill_mode)) | Some(DrawMode::Outlined { fill_mode, .. }) => {
+                    fill_mode.options.fill_rule
+                }
+                _ => FillRule::NonZero,
+            };
+            Some(shape_polygons(path, transform, rule))
+        })
+        .collect::<Vec<_>>();
+    let bottom = shapes[0];
+    let index = ordered.0.iter().position(|o| *o == bottom)?;
+    let mut record = ShapeRecord::capture(bottom, records)?;
+    let (shape, center) = polygon_path(&boolean_result(operation, polygons), &record.transform())?;
+    record.translation = record.transform().transform_point(center.extend(0.0));
+    record.name = None;
+    record.connector = None;
+    record.geometry = GeometryRecord::Custom(shape);
+    let (e, _) = spawn_shape(commands, &record);
+    ordered.0.insert(index + 1, e);
+    history.send(HistoryEvent(EditAction::Created { entity: e }));
+    delete_shapes(commands, shapes, ordered, records, history, changed);
+    Some(e)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn apply_path_ops(
+    mut commands: Commands,
+    mut pending_selection: Local<Vec<Entity>>,
+    mut events: EventReader<PathOpEvent>,
+    mut ordered: ResMut<OrderedShapes>,
+    mut selection: Query<&mut Selection, (With<ShapeBase>, Without<Parent>)>,
+    paths: Query<(&CustomShapeRaw, &Transform)>,
+    primitives: Query<&PrimitiveShape>,
+    outlines: Query<(&Path, &Transform, Option<&DrawMode>), Without<Locked>>,
+    records: Query<ShapeRecordQuery>,
+    mut history: EventWriter<HistoryEvent>,
+    mut changed: EventWriter<ChangedOrderEvent>,
+) {
+    // New shapes only exist once the commands of the previous frame were applied.
+    for e in pending_selection.drain(..) {
+        if let Ok(mut s) = selection.get_mut(e) {
+            s.set_selected(true);
+        }
+    }
+    for PathOpEvent(operation) in events.iter() {
+        // Bottom to top, the result takes the place of the bottom shape.
+        let selected = ordered
+            .0
+            .iter()
+            .copied()
+            .filter(|e| matches!(selection.get(*e), Ok(s) if s.selected()))
+            .filter(|e| outlines.contains(*e))
+            .collect::<Vec<_>>();
+        if *operation == PathOperation::ToPath {
+            for e in selected {
+                if let Ok(primitive) = primitives.get(e) {
+                    let after = GeometryRecord::Custom(primitive_path(primitive));
+                    apply_geometry(&mut commands, e, &after);
+                    history.send(HistoryEvent(EditAction::Reshaped {
+                        entity: e,
+                        before: GeometryRecord::Primitive(primitive.clone()),
+                        after,
+                    }));
+                }
+            }
+            continue;
+        }
+        if selected.len() < 2 {
+            continue;
+        }
+        if *operation != PathOperation::Combine {
+            pending_selection.extend(apply_boolean(
+                &mut commands,
+                *operation,
+                &selected,
+                &mut ordered,
+                &outlines,
+                &records,
+                &mut history,
+                &mut changed,
+            ));
+            continue;
+        }
+        let selected = selected
+            .into_iter()
+            .filter_map(|e| Some((e, paths.get(e).ok()?)))
+            .collect::<Vec<_>>();
+        let ((target, (shape, transform)), rest) = match selected.split_first() {
+            Some(split) if selected.len() > 1 => split,
+            _ => continue,
+        };
+        let others = rest.iter().map(|(_, (s, t))| (*s, *t)).collect::<Vec<_>>();
+        let after = GeometryRecord::Custom(combine_paths((shape, transform), &others));
+        apply_geometry(&mut commands, *target, &after);
+        history.send(HistoryEvent(EditAction::Reshaped {
+            entity: *target,
+            before: GeometryRecord::Custom((*shape).clone()),
+            after,
+        }));
+        let removed = rest.iter().map(|(e, _)| *e).collect::<Vec<_>>();
+        delete_shapes(
+            &mut commands,
+            &removed,
+            &ordered,
+            &records,
+            &mut history,
+            &mut changed,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::custom_shape::ShapeSegment::Line;
+    use bevy_prototype_lyon::prelude::tess;
+
+    #[test]
+    fn combined_contours_keep_their_place() {
+        let target = CustomShapeRaw {
+            segments: vec![
+                Line(Point::new(10.0, 0.0)),
+                Line(Point::new(10.0, 10.0)),
+                Line(Point::new(0.0, 10.0)),
+            ],
+            closed: true,
+            origin: Vec2::new(-5.0, -5.0),
+            subpaths: vec![],
+        };
+        let other = CustomShapeRaw {
+            segments: vec![Line(Point::new(2.0, 0.0)), Line(Point::new(2.0, 2.0))],
+            closed: false,
+            origin: Vec2::ZERO,
+            subpaths: vec![Subpath {
+                start: Point::new(1.0, 1.0),
+                segments: vec![Line(Point::new(1.0, 2.0))],
+                closed: true,
+            }],
+        };
+        let combined = combine_paths(
+            (&target, &Transform::from_xyz(100.0, 0.0, 0.0)),
+            &[(&other, &Transform::from_xyz(110.0, 10.0, 0.0))],
+        );
+        assert_eq!(combined.segments, target.segments);
+        assert_eq!(
+            combined.subpaths,
+            vec![
+                Subpath {
+                    start: Point::new(15.0, 15.0),
+                    segments: vec![Line(Point::new(17.0, 15.0)), Line(Point::new(17.0, 17.0))],
+                    closed: false,
+                },
+                Subpath {
+                    start: Point::new(16.0, 16.0),
+                    segments: vec![Line(Point::new(16.0, 17.0))],
+                    closed: true,
+                },
+            ]
+        );
+    }
+
+    fn square(x: f64, y: f64, size: f64) -> Vec<(f64, f64)> {
+        vec![
+            (x, y),
+            (x + size, y),
+            (x + size, y + size),
+            (x, y + size),
+            (x, y),
+        ]
+    }
+
+    fn area(polygons: &MultiPolygon) -> f64 {
+        let ring_area = |ring: &LineString| {
+            signed_area(&ring.coords().map(|c| (c.x, c.y)).collect::<Vec<_>>()).abs()
+        };
+        polygons
+            .iter()
+            .map(|p| ring_area(p.exterior()) - p.interiors().iter().map(ring_area).sum::<f64>())
+            .sum()
+    }
+
+    fn square_path(squares: &[(f32, f32, f32)], clockwise: &[bool]) -> Path {
+        let mut builder = tess::path::Path::builder();
+        for (&(x, y, size), &clockwise) in squares.iter().zip(clockwise) {
+            let mut corners = [
+                Point::new(x, y),
+                Point::new(x + size, y),
+                Point::new(x + size, y + size),
+                Point::new(x, y + size),
+            ];
+            if clockwise {
+                corners.reverse();
+            }
+            builder.begin(corners[0]);
+            for corner in &corners[1..] {
+                builder.line_to(*corner);
+            }
+            builder.end(true);
+        }
+        Path(builder.build())
+    }
+
+    #[test]
+    fn nothing_selected_gives_nothing() {
+        assert!(boolean_result(PathOperation::Union, vec![]).0.is_empty());
+    }
+
+    #[test]
+    fn touching_shapes_are_merged() {
+        let shapes = vec![
+            ring_polygon(square(0.0, 0.0, 10.0)),
+            ring_polygon(square(10.0, 0.0, 10.0)),
+        ];
+        let union = boolean_result(PathOperation::Union, shapes.clone());
+        assert_eq!(union.0.len(), 1);
+        assert!(union.0[0].interiors().is_empty());
+        assert!((area(&union) - 200.0).abs() < 1e-6);
+        // Shapes that only share an edge have nothing in common.
+        let intersection = boolean_result(PathOperation::Intersection, shapes);
+        assert!(area(&intersection).abs() < 1e-6);
+    }
+
+    #[test]
+    fn contained_shapes_cut_holes() {
+        let shapes = vec![
+            ring_polygon(square(0.0, 0.0, 10.0)),
+            ring_polygon(square(2.0, 2.0, 2.0)),
+        ];
+        let difference = boolean_result(PathOperation::Difference, shapes.clone());
+        assert_eq!(difference.0.len(), 1);
+        assert_eq!(difference.0[0].interiors().len(), 1);
+        assert!((area(&difference) - 96.0).abs() < 1e-6);
+        let union = boolean_result(PathOperation::Union, shapes.clone());
+        assert!(union.0[0].interiors().is_empty());
+        assert!((area(&union) - 100.0).abs() < 1e-6);
+        let intersection = boolean_result(PathOperation::Intersection, shapes);
+        assert!((area(&intersection) - 4.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn fill_rules_decide_about_holes() {
+        let squares = [(0.0, 0.0, 10.0), (2.0, 2.0, 2.0)];
+        let transform = Transform::IDENTITY;
+        let same = square_path(&squares, &[false, false]);
+        let against = square_path(&squares, &[false, true]);
+        let even_odd = shape_polygons(&same, &transform, FillRule::EvenOdd);
+        assert!((area(&even_odd) - 96.0).abs() < 1e-6);
+        let non_zero = shape_polygons(&same, &transform, FillRule::NonZero);
+        assert!((area(&non_zero) - 100.0).abs() < 1e-6);
+        let non_zero = shape_polygons(&against, &transform, FillRule::NonZero);
+        assert!((area(&non_zero) - 96.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn holes_become_subpaths() {
+        let polygons = MultiPolygon(vec![Polygon::new(
+            LineString::from(square(0.0, 0.0, 10.0)),
+            vec![LineString::from(square(2.0, 2.0, 2.0))],
+        )]);
+        let transform = Transform::from_xyz(100.0, 0.0, 0.0);
+        let (shape, center) = polygon_path(&polygons, &transform).unwrap();
+        assert_eq!(center, Vec2::new(-95.0, 5.0));
+        assert_eq!(shape.origin, Vec2::new(-5.0, -5.0));
+        assert_eq!(shape.segments.len(), 3);
+        assert!(shape.closed);
+        assert_eq!(shape.subpaths.len(), 1);
+        assert_eq!(shape.subpaths[0].start, Point::new(2.0, 2.0));
+        assert!(shape.subpaths[0].closed);
+    }
+
+    #[test]
+    fn empty_results_have_no_path() {
+        assert!(polygon_path(&MultiPolygon(vec![]), &Transform::IDENTITY).is_none());
+    }
+}
@@ -1,8 +1,8 @@
-use crate::MouseMovement;
+use crate::helpers::{shape_bounds, transformed_aabb, BoundsQuery};
+use crate::{MouseMovement, ShapeBase};
 use bevy::app::PluginGroupBuilder;
 use bevy::ecs::schedule::ShouldRun;
 use bevy::prelude::*;
-use bevy::sprite::Mesh2dHandle;
 use bevy::ui::FocusPolicy;
 use bevy_mod_picking::{
     mesh_events_system, mesh_focus, mesh_selection, pause_for_picking_blockers, Hover, NoDeselect,
@@ -51,14 +51,16 @@ struct HighlightRect;
 #[derive(Component)]
 pub struct TransformScalePick {
     pub location: (f32, f32),
-    pub entity: Option<Entity>,
+    pub entities: Vec<Entity>,
     pub size: Vec2,
+    pub center: Vec2,
 }
 
 #[derive(Component)]
 pub struct TransformRotationPick {
     pub location: (f32, f32),
-    pub entity: Option<Entity>,
+    pub entities: Vec<Entity>,
+    pub center: Vec2,
 }
 pub fn spawn_highlight_rectangle(mut commands: Commands) {
     let bundle = GeometryBuilder::build_as(
@@ -106,8 +108,9 @@ pub fn spawn_highlight_rectangle(mut commands: Commands) {
                     .insert((
                         TransformScalePick {
                             location: x,
-                            entity: None,
+                            entities: Vec::new(),
                             size: Vec2::ZERO,
+                            center: Vec2::ZERO,
                         },
                         Visibility { is_visible: false },
                         PickableBundle::default(),
@@ -130,12 +133,15 @@ pub fn spawn_highlight_rectangle(mut commands: Commands) {
                             },
                             Transform::from_translation(Vec3::new(0.0, 0.0, 0.0)),
                         );
-                        p.spawn(b)
-                            .insert((TransformRotationPick {
+                        p.spawn(b).insert((
+                            TransformRotationPick {
                                 location: x,
-                                entity: None,
-                            },PickableBundle::default(),
-                            NoDeselect));
+                                entities: Vec::new(),
+                                center: Vec2::ZERO,
+                            },
+                            PickableBundle::default(),
+                            NoDeselect,
+                        ));
                     });
             }
         });
@@ -143,92 +149,110 @@ pub fn spawn_highlight_rectangle(mut commands: Commands) {
 
 fn highlight_selected(
     points: Res<Assets<Mesh>>,
-    handles: Query<
-        (&Selection, &Mesh2dHandle, &Transform, Entity),
-        (
-            Without<HighlightRect>,
-            (Without<TransformScalePick>, Without<TransformRotationPick>),
-        ),
-    >,
+    handles: Query<(&Selection, Entity), (With<ShapeBase>, Without<Parent>)>,
+    bounds: BoundsQuery,
     mut rect: Query<
         (&mut Transform, &mut Path, &DrawMode),
         (
             With<HighlightRect>,
             (Without<TransformScalePick>, Without<TransformRotationPick>),
+            Without<ShapeBase>,
         ),
     >,
     mut pickers: Query<
         (&mut Transform, &mut TransformScalePick, &mut Visibility),
-        (Without<HighlightRect>, Without<TransformRotationPick>),
+        (
+            Without<HighlightRect>,
+            Without<TransformRotationPick>,
+            Without<ShapeBase>,
+        ),
     >,
     mut rot_pickers: Query<
         (&mut Transform, &mut TransformRotationPick),
-        (Without<HighlightRect>, Without<TransformScalePick>),
+        (
+            Without<HighlightRect>,
+            Without<TransformScalePick>,
+            Without<ShapeBase>,
+        ),
     >,
     cam: Query<&OrthographicProjection>,
 ) {
     if let Ok((mut rect_transform, mut path, draw_mode)) = rect.get_single_mut() {
-        if let Some((handle, transform, entity)) = handles
+        let selected = handles
             .iter()
-            .filter_map(|(n, x, y, e)| {
+            .filter_map(|(n, e)| {
                 if n.selected() {
-                    return Some((x, y, e));
+                    return Some((shape_bounds(e, &points, &bounds)?, bounds.get(e).ok()?.2, e));
                 }
                 None
             })
-            .next()
-        {
-            if let Some(mesh) = points.get(&handle.0.clone()) {
-                if let Some(aabb) = mesh.compute_aabb() {
-                    *rect_transform = transform
-                        .with_translation(
-                            transform.translation
-                                + Vec3::from(transform.rotation.mul_vec3a(aabb.center))
-                                    * transform.scale
-                                    * Vec3::new(1.0, 1.0, 0.0)
-                                + Vec3::new(0.0, 0.0, 100.0),
-                        )
-                        .with_scale(Vec3::splat(1.0));
-
-                    if let DrawMode::Stroke(stroke) = draw_mode {
-                        *path = ShapePath::build_as(&shapes::Rectangle {
-                            extents: aabb.half_extents.truncate()
-                                * 2.0
-                                * transform.scale.truncate()
-                                + Vec2::splat(stroke.options.line_width),
-                            origin: RectangleOrigin::Center,
-                        });
-                    }
-                    for (mut p_transform, mut p, mut visibility) in pickers.iter_mut() {
-                        *p_transform = Transform::from_translation(Vec3::new(
-                            p.location.0 * aabb.half_extents.x * transform.scale.x,
-                            p.location.1 * aabb.half_extents.y * transform.scale.y,
-                            0.0,
-                        ));
-                        p.entity = Some(entity);
-                        p.size = aabb.half_extents.truncate() * 2.0 * transform.scale.truncate();
-                        visibility.is_visible = true;
-                    }
-                    if let Ok(cam) = cam.get_single() {
-                        for (mut p_transform, mut p) in rot_pickers.iter_mut() {
-                            *p_transform = Transform::from_translation(Vec3::new(
-                                p.location.0 * 3.125 * cam.scale,
-                                p.location.1 * 3.125 * cam.scale,
-                                -1.0,
-                            ));
-                            p.entity = Some(entity);
-                        }
-                    }
+            .collect::<Vec<_>>();
+        let line_width = match draw_mode {
+            DrawMode::Stroke(stroke) => stroke.options.line_width,
+            _ => 0.0,
+        };
+        let (size, center) = match selected.as_slice() {
+            [] => {
+                *path = ShapePath::build_as(&shapes::Rectangle {
+                    extents: Vec2::ZERO,
+                    origin: RectangleOrigin::Center,
+                });
+                for (_, mut p, mut visibility) in pickers.iter_mut() {
+                    p.entities.clear();
+                    visibility.is_visible = false;
+                }
+                for (_, mut p) in rot_pickers.iter_mut() {
+                    p.entities.clear();
                 }
+                return;
             }
-        } else {
-            *path = ShapePath::build_as(&shapes::Rectangle {
-                extents: Vec2::ZERO,
-                origin: RectangleOrigin::Center,
-            });
-            for (_, mut p, mut visibility) in pickers.iter_mut() {
-                p.entity = None;
-                visibility.is_visible = false;
+            [(aabb, transform, _)] => {
+                *rect_transform = transform
+                    .with_translation(
+                        transform.translation
+                            + Vec3::from(transform.rotation.mul_vec3a(aabb.center))
+                                * transform.scale
+                                * Vec3::new(1.0, 1.0, 0.0)
+                            + Vec3::new(0.0, 0.0, 100.0),
+                    )
+                    .with_scale(Vec3::splat(1.0));
+                (
+                    aabb.half_extents.truncate() * 2.0 * transform.scale.truncate(),
+                    transform.translation.truncate(),
+                )
+            }
+            _ => {
+                let bounds = selected
+                    .iter()
+                    .map(|(aabb, transform, _)| transformed_aabb(aabb, transform))
+                    .reduce(|a, b| a.union(b))
+                    .unwrap_or_default();
+                *rect_transform = Transform::from_translation(bounds.center().extend(100.0));
+                (bounds.size(), bounds.center())
+            }
+        };
+        let entities = selected.iter().map(|(_, _, e)| *e).collect::<Vec<_>>();
+        *path = ShapePath::build_as(&shapes::Rectangle {
+            extents: size + Vec2::splat(line_width),
+            origin: RectangleOrigin::Center,
+        });
+        for (mut p_transform, mut p, mut visibility) in pickers.iter_mut() {
+            *p_transform =
+                Transform::from_translation((Vec2::from(p.location) * size / 2.0).extend(0.0));
+            p.entities = entities.clone();
+            p.size = size;
+            p.center = center;
+            visibility.is_visible = true;
+        }
+        if let Ok(cam) = cam.get_single() {
+            for (mut p_transform, mut p) in rot_pickers.iter_mut() {
+                *p_transform = Transform::from_translation(Vec3::new(
+                    p.location.0 * 3.125 * cam.scale,
+                    p.location.1 * 3.125 * cam.scale,
+                    -1.0,
+                ));
+                p.entities = entities.clone();
+                p.center = center;
             }
         }
     }
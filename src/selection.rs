@@ -0,0 +1,215 @@
+use crate::group::root_shape;
+use crate::helpers::{
+    ctrl_pressed, rects_overlap, shape_bounds, shift_pressed, transformed_aabb, BoundsQuery,
+};
+use crate::layers::{Hidden, Locked};
+use crate::{MouseMovement, Moving, ShapeBase, Tool, ToolType};
+use bevy::prelude::*;
+use bevy_mod_picking::{PickingCamera, PickingSystem, Selection};
+use bevy_prototype_lyon::prelude::{
+    shapes, DrawMode, GeometryBuilder, Path, RectangleOrigin, ShapePath, StrokeMode,
+};
+
+pub struct SelectionPlugin;
+
+impl Plugin for SelectionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(spawn_marquee)
+            .add_system_to_stage(
+                CoreStage::First,
+                additive_selection.after(PickingSystem::Selection),
+            )
+            .add_system(marquee_selection)
+            .add_system(select_all);
+    }
+}
+
+#[derive(Component)]
+struct Marquee;
+
+#[derive(Default)]
+struct ClickState {
+    previous: Vec<Entity>,
+    pressed_on: Option<Entity>,
+    position: Vec2,
+}
+
+fn additive_modifier(input: &Input<KeyCode>) -> bool {
+    shift_pressed(input) || ctrl_pressed(input)
+}
+
+fn spawn_marquee(mut commands: Commands) {
+    commands
+        .spawn(GeometryBuilder::build_as(
+            &shapes::Rectangle {
+                extents: Vec2::ZERO,
+                origin: RectangleOrigin::Center,
+            },
+            DrawMode::Stroke(StrokeMode::color(Color::BLUE)),
+            Transform::from_translation(Vec3::new(0.0, 0.0, 200.0)),
+        ))
+        .insert(Marquee);
+}
+
+/// Runs right after the picking selection, which always replaces the current selection,
+/// and puts back what a Shift/Ctrl-click or a click on an already selected shape should keep.
+/// Clicking a grouped shape selects its outermost group instead.
+#[allow(clippy::too_many_arguments)]
+fn additive_selection(
+    mut state: Local<ClickState>,
+    input: Res<Input<KeyCode>>,
+    mouse_input: Res<Input<MouseButton>>,
+    mouse: Res<MouseMovement>,
+    cameras: Query<&PickingCamera>,
+    projection: Query<&OrthographicProjection>,
+    parents: Query<&Parent, With<ShapeBase>>,
+    mut shapes: Query<(Entity, &mut Selection), With<ShapeBase>>,
+) {
+    for (e, mut selection) in shapes.iter_mut() {
+        if selection.selected() && parents.contains(e) {
+            selection.set_selected(false);
+        }
+    }
+    if mouse_input.just_pressed(MouseButton::Left) && !mouse.over_ui {
+        let clicked = cameras
+            .get_single()
+            .ok()
+            .and_then(|cam| cam.get_nearest_intersection())
+            .map(|(e, _)| e)
+            .filter(|e| shapes.contains(*e))
+            .map(|e| root_shape(e, &parents));
+        let keep_previous =
+            additive_modifier(&input) || matches!(clicked, Some(e) if state.previous.contains(&e));
+        if keep_previous {
+            for e in state.previous.iter() {
+                if let Ok((_, mut selection)) = shapes.get_mut(*e) {
+                    selection.set_selected(true);
+                }
+            }
+        }
+        if let Some(e) = clicked {
+            if let Ok((_, mut selection)) = shapes.get_mut(e) {
+                if additive_modifier(&input) {
+                    selection.set_selected(!state.previous.contains(&e));
+                } else {
+                    selection.set_selected(true);
+                }
+            }
+            if keep_previous && !additive_modifier(&input) {
+                state.pressed_on = Some(e);
+                state.position = mouse.position;
+            }
+        }
+    }
+    if mouse_input.just_released(MouseButton::Left) {
+        if let Some(clicked) = state.pressed_on.take() {
+            let tolerance = projection.get_single().map_or(1.0, |p| p.scale) * 3.0;
+            if mouse.position.distance(state.position) < tolerance {
+                for (e, mut selection) in shapes.iter_mut() {
+                    selection.set_selected(e == clicked);
+                }
+            }
+        }
+    }
+    state.previous = shapes
+        .iter()
+        .filter_map(|(e, s)| if s.selected() { Some(e) } else { None })
+        .collect();
+}
+
+#[allow(clippy::too_many_arguments)]
+fn marquee_selection(
+    mut start: Local<Option<Vec2>>,
+    tool: Res<Tool>,
+    input: Res<Input<KeyCode>>,
+    mouse_input: Res<Input<MouseButton>>,
+    mouse: Res<MouseMovement>,
+    cameras: Query<&PickingCamera>,
+    projection: Query<&OrthographicProjection>,
+    meshes: Res<Assets<Mesh>>,
+    bounds: BoundsQuery,
+    mut shapes: Query<
+        (Entity, &mut Selection),
+        (
+            With<ShapeBase>,
+            Without<Moving>,
+            Without<Parent>,
+            Without<Locked>,
+            Without<Hidden>,
+        ),
+    >,
+    mut marquee: Query<
+        (&mut Path, &mut Transform, &mut DrawMode),
+        (With<Marquee>, Without<ShapeBase>),
+    >,
+) {
+    let (mut path, mut marquee_transform, mut draw_mode) = match marquee.get_single_mut() {
+        Ok(m) => m,
+        Err(_) => return,
+    };
+    if mouse_input.just_pressed(MouseButton::Left)
+        && tool.tool == ToolType::None
+        && !mouse.over_ui
+        && cameras
+            .get_single()
+            .map_or(true, |cam| cam.get_nearest_intersection().is_none())
+    {
+        *start = Some(mouse.position);
+    }
+    let origin = match *start {
+        Some(origin) => origin,
+        None => return,
+    };
+    if mouse_input.just_released(MouseButton::Left) {
+        let area = Rect::from_corners(origin, mouse.position);
+        let additive = additive_modifier(&input);
+        for (e, mut selection) in shapes.iter_mut() {
+            let inside = match (shape_bounds(e, &meshes, &bounds), bounds.get(e)) {
+                (Some(aabb), Ok((_, _, transform))) => {
+                    rects_overlap(area, transformed_aabb(&aabb, transform))
+                }
+                _ => false,
+            };
+            if inside {
+                selection.set_selected(true);
+            } else if !additive {
+                selection.set_selected(false);
+            }
+        }
+        *path = ShapePath::build_as(&shapes::Rectangle {
+            extents: Vec2::ZERO,
+            origin: RectangleOrigin::Center,
+        });
+        *start = None;
+    } else if mouse_input.pressed(MouseButton::Left) {
+        let scale = projection.get_single().map_or(1.0, |p| p.scale);
+        *path = ShapePath::build_as(&shapes::Rectangle {
+            extents: (mouse.position - origin).abs(),
+            origin: RectangleOrigin::Center,
+        });
+        *marquee_transform =
+            marquee_transform.with_translation(((origin + mouse.position) / 2.0).extend(200.0));
+        *draw_mode = DrawMode::Stroke(StrokeMode::new(Color::BLUE, scale * 1.5));
+    }
+}
+
+fn select_all(
+    input: Res<Input<KeyCode>>,
+    mouse: Res<MouseMovement>,
+    mut shapes: Query<
+        &mut Selection,
+        (
+            With<ShapeBase>,
+            Without<Moving>,
+            Without<Parent>,
+            Without<Locked>,
+            Without<Hidden>,
+        ),
+    >,
+) {
+    if ctrl_pressed(&input) && input.just_pressed(KeyCode::A) && !mouse.over_ui {
+        for mut selection in shapes.iter_mut() {
+            selection.set_selected(true);
+        }
+    }
+}
@@ -1,10 +1,16 @@
 use crate::custom_shape::CustomShapeRaw;
+use crate::grid::Grid;
+use crate::group::root_shape;
+use crate::history::{EditAction, HistoryEvent};
+use crate::layers::Locked;
 use crate::picking_helpers::{TransformRotationPick, TransformScalePick};
-use crate::{global_vec_to_local, MouseMovement, Moving, ShapeBase};
+use crate::snapping::{ObjectSnap, ShapeBounds, SNAP_DISTANCE};
+use crate::{global_vec_to_local, MouseMovement, Moving, ShapeBase, Tool, ToolType};
 use bevy::prelude::*;
 use bevy::sprite::Mesh2dHandle;
 use bevy_mod_picking::{PickingCamera, Selection};
 use bevy_prototype_lyon::prelude::{Path, ShapePath};
+use iyes_loopless::prelude::*;
 
 pub struct ShapeTransformPlugin;
 
@@ -25,111 +31,241 @@ impl OverEntity {
 impl Plugin for ShapeTransformPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<OverEntity>()
-            .add_system(move_shape)
-            .add_system(scale_shape)
-            .add_system(rotate_shape)
+            .add_system_set(
+                ConditionSet::new()
+                    .run_if(should_transform)
+                    .with_system(move_shape)
+                    .with_system(scale_shape)
+                    .with_system(rotate_shape)
+                    .into(),
+            )
             .add_system(update_focused_shape)
             .add_system(debug_scale);
     }
 }
 
+fn should_transform(tool: Res<Tool>) -> bool {
+    tool.tool != ToolType::EditPoints
+}
+
 #[derive(Default)]
 struct Moved {
-    e: Option<Entity>,
+    entities: Vec<(Entity, Transform)>,
     pos_pressed: Vec2,
+    bounds: Option<Rect>,
+    targets: Vec<Rect>,
 }
 
 #[derive(Default)]
 struct Rotated {
-    e: Option<Entity>,
+    entities: Vec<(Entity, Transform)>,
     pos_pressed: Vec2,
-    base_rotation: f32,
+    center: Vec2,
+}
+
+fn record_transform(
+    history: &mut EventWriter<HistoryEvent>,
+    e: Entity,
+    before: Transform,
+    after: Transform,
+) {
+    if before != after {
+        history.send(HistoryEvent(EditAction::Transformed {
+            entity: e,
+            before,
+            after,
+        }));
+    }
+}
+
+/// Locked shapes stay where they are, even when selected from the Objects list.
+type TransformableShapes<'w, 's> = Query<
+    'w,
+    's,
+    (Entity, &'static mut Transform, &'static Selection),
+    (With<ShapeBase>, Without<Locked>),
+>;
+
+fn record_transforms(
+    history: &mut EventWriter<HistoryEvent>,
+    entities: Vec<(Entity, Transform)>,
+    query: &TransformableShapes,
+) {
+    for (e, before) in entities {
+        if let Ok((_, transform, _)) = query.get(e) {
+            record_transform(history, e, before, *transform);
+        }
+    }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn move_shape(
     mut moved: Local<Moved>,
-    mut query: Query<&mut Transform, (With<Selection>, With<ShapeBase>)>,
+    mut query: TransformableShapes,
+    parents: Query<&Parent, With<ShapeBase>>,
     over_entity: Res<OverEntity>,
     mouse_input: Res<Input<MouseButton>>,
     mouse: Res<MouseMovement>,
+    grid: Res<Grid>,
+    mut snap: ResMut<ObjectSnap>,
+    shape_bounds: Res<ShapeBounds>,
+    projection: Query<&OrthographicProjection>,
+    mut history: EventWriter<HistoryEvent>,
 ) {
     if mouse_input.just_released(MouseButton::Left) {
-        moved.e = None;
+        snap.clear();
+        let entities = std::mem::take(&mut moved.entities);
+        record_transforms(&mut history, entities, &query);
         return;
     }
     if mouse_input.just_pressed(MouseButton::Left) {
-        if let Some(e) = over_entity.entity {
-            if let Ok(transform) = query.get_mut(e) {
-                moved.pos_pressed = mouse.position - transform.translation.truncate();
-                moved.e = Some(e);
+        if let Some(e) = over_entity.entity.map(|e| root_shape(e, &parents)) {
+            if let Ok((_, transform, selection)) = query.get(e) {
+                moved.pos_pressed = mouse.position;
+                moved.entities = if selection.selected() {
+                    query
+                        .iter()
+                        .filter(|(_, _, s)| s.selected())
+                        .map(|(e, t, _)| (e, *t))
+                        .collect()
+                } else {
+                    vec![(e, *transform)]
+                };
+                let entities = moved.entities.iter().map(|(e, _)| *e).collect::<Vec<_>>();
+                moved.bounds = shape_bounds.union(&entities);
+                moved.targets = shape_bounds.others(&entities);
             }
         }
     }
-    if mouse_input.pressed(MouseButton::Left) {
-        if let Some(e) = moved.e {
-            if let Ok(mut transform) = query.get_mut(e) {
-                *transform = transform.with_translation(
-                    (mouse.position - moved.pos_pressed).extend(transform.translation.z),
-                );
+    if mouse_input.pressed(MouseButton::Left) && !moved.entities.is_empty() {
+        let mut delta = grid.snap(mouse.position - moved.pos_pressed);
+        if let Some(bounds) = moved.bounds {
+            let rect = Rect {
+                min: bounds.min + delta,
+                max: bounds.max + delta,
+            };
+            let scale = projection.get_single().map_or(1.0, |p| p.scale);
+            delta += snap.snap(
+                &[rect.min.x, rect.center().x, rect.max.x],
+                &[rect.min.y, rect.center().y, rect.max.y],
+                rect,
+                &moved.targets,
+                SNAP_DISTANCE * scale,
+            );
+        }
+        for (e, before) in moved.entities.iter() {
+            if let Ok((_, mut transform, _)) = query.get_mut(*e) {
+                *transform = transform.with_translation(before.translation + delta.extend(0.0));
             }
         }
     }
 }
 #[derive(Default)]
 struct Scaled {
-    e: Option<Entity>,
+    entities: Vec<(Entity, Transform)>,
     factor: (f32, f32),
     pos_pressed: Vec2,
-    orig_scale: Vec3,
+    handle: Vec2,
     orig_size: Vec2,
-    orig_translat: Vec3,
-    global_picker: Vec3,
+    center: Vec2,
+    targets: Vec<Rect>,
 }
+#[allow(clippy::too_many_arguments)]
 fn scale_shape(
     mut scaled: Local<Scaled>,
-    mut query: Query<&mut Transform, (With<Selection>, With<ShapeBase>)>,
-    mut selector_query: Query<(&TransformScalePick, &GlobalTransform)>,
+    mut query: TransformableShapes,
+    selector_query: Query<(&TransformScalePick, &GlobalTransform)>,
     over_entity: Res<OverEntity>,
     mouse_input: Res<Input<MouseButton>>,
     mouse: Res<MouseMovement>,
+    grid: Res<Grid>,
+    mut snap: ResMut<ObjectSnap>,
+    shape_bounds: Res<ShapeBounds>,
+    projection: Query<&OrthographicProjection>,
+    mut history: EventWriter<HistoryEvent>,
 ) {
     if mouse_input.just_released(MouseButton::Left) {
-        scaled.e = None;
+        snap.clear();
+        let entities = std::mem::take(&mut scaled.entities);
+        record_transforms(&mut history, entities, &query);
         return;
     }
     if mouse_input.just_pressed(MouseButton::Left) {
         if let Some(e) = over_entity.entity {
-            if let Ok((transform_pick, pick_transform)) = selector_query.get_mut(e) {
-                scaled.e = transform_pick.entity;
+            if let Ok((transform_pick, pick_transform)) = selector_query.get(e) {
                 scaled.factor = transform_pick.location;
+                scaled.handle = pick_transform.translation().truncate();
                 scaled.orig_size = transform_pick.size;
-                scaled.global_picker = pick_transform.translation();
-                if let Ok(transform) = query.get(scaled.e.unwrap()) {
-                    scaled.pos_pressed = mouse.position;
-                    scaled.orig_scale = transform.scale;
-                    scaled.orig_translat = transform.translation
-                }
+                scaled.center = transform_pick.center;
+                scaled.pos_pressed = mouse.position;
+                scaled.entities = transform_pick
+                    .entities
+                    .iter()
+                    .filter_map(|e| query.get(*e).ok().map(|(e, t, _)| (e, *t)))
+                    .collect();
+                scaled.targets = shape_bounds.others(&transform_pick.entities);
             }
         }
     }
-    if mouse_input.pressed(MouseButton::Left) {
-        if let Some(e) = scaled.e {
-            if let Ok(mut transform) = query.get_mut(e) {
-                let not_rotated_position = mouse.position - scaled.pos_pressed;
-                let whole = scaled.orig_size / scaled.orig_scale.truncate();
+    if mouse_input.pressed(MouseButton::Left) && !scaled.entities.is_empty() {
+        let fact = Vec2::from(scaled.factor);
+        // The dragged handle, not the cursor, lands on the grid and on other shapes.
+        let mut handle = grid.snap(scaled.handle + mouse.position - scaled.pos_pressed);
+        let scale = projection.get_single().map_or(1.0, |p| p.scale);
+        let xs = if fact.x != 0.0 {
+            vec![handle.x]
+        } else {
+            vec![]
+        };
+        let ys = if fact.y != 0.0 {
+            vec![handle.y]
+        } else {
+            vec![]
+        };
+        handle += snap.snap(
+            &xs,
+            &ys,
+            Rect::from_corners(handle, handle),
+            &scaled.targets,
+            SNAP_DISTANCE * scale,
+        );
+        let not_rotated_position = handle - scaled.handle;
+        if let [(e, before)] = scaled.entities.as_slice() {
+            if let Ok((_, mut transform, _)) = query.get_mut(*e) {
+                let whole = scaled.orig_size / before.scale.truncate();
                 let r = transform.rotation.to_axis_angle();
                 let rotation = r.0.z * r.1;
                 let local_position = global_vec_to_local(not_rotated_position, rotation);
-                let fact = Vec2::from(scaled.factor);
                 let f = local_position * fact + scaled.orig_size;
                 let scale = f / whole;
                 let offset = global_vec_to_local(local_position * fact.abs(), -rotation);
                 *transform = Transform {
-                    translation: scaled.orig_translat + offset.extend(0.0) / 2.0,
+                    translation: before.translation + offset.extend(0.0) / 2.0,
                     rotation: transform.rotation,
                     scale: scale.extend(1.0),
                 }
             }
+            return;
+        }
+        // Several shapes scale together relative to the side opposite to the dragged handle.
+        let size = not_rotated_position * fact + scaled.orig_size;
+        let k = Vec2::select(
+            scaled.orig_size.cmpgt(Vec2::ZERO),
+            size / scaled.orig_size,
+            Vec2::ONE,
+        );
+        let anchor = scaled.center - fact * scaled.orig_size / 2.0;
+        for (e, before) in scaled.entities.iter() {
+            if let Ok((_, mut transform, _)) = query.get_mut(*e) {
+                let translation = anchor + (before.translation.truncate() - anchor) * k;
+                let x_axis = (before.rotation * Vec3::X).truncate() * k;
+                let y_axis = (before.rotation * Vec3::Y).truncate() * k;
+                *transform = Transform {
+                    translation: translation.extend(before.translation.z),
+                    rotation: before.rotation,
+                    scale: before.scale * Vec3::new(x_axis.length(), y_axis.length(), 1.0),
+                }
+            }
         }
     }
 }
@@ -137,38 +273,47 @@ fn scale_shape(
 fn rotate_shape(
     mut rotated: Local<Rotated>,
     rot_pickers: Query<&TransformRotationPick>,
-    mut transformable_objects: Query<&mut Transform, With<ShapeBase>>,
+    mut query: TransformableShapes,
     over_entity: Res<OverEntity>,
     mouse_input: Res<Input<MouseButton>>,
     mouse: Res<MouseMovement>,
+    mut history: EventWriter<HistoryEvent>,
 ) {
     if mouse_input.just_released(MouseButton::Left) {
-        rotated.e = None;
+        let entities = std::mem::take(&mut rotated.entities);
+        record_transforms(&mut history, entities, &query);
         return;
     }
 
     if mouse_input.just_pressed(MouseButton::Left) {
         if let Some(e) = over_entity.entity {
             if let Ok(picker) = rot_pickers.get(e) {
-                rotated.e = picker.entity;
                 rotated.pos_pressed = mouse.position;
-                if let Some(ent) = rotated.e {
-                    if let Ok(transform) = transformable_objects.get(ent) {
-                        let r = transform.rotation.to_axis_angle();
-                        rotated.base_rotation = r.0.z * r.1;
-                    }
-                }
+                rotated.center = picker.center;
+                rotated.entities = picker
+                    .entities
+                    .iter()
+                    .filter_map(|e| query.get(*e).ok().map(|(e, t, _)| (e, *t)))
+                    .collect();
             }
         }
     }
 
     if mouse_input.pressed(MouseButton::Left) {
-        if let Some(e) = rotated.e {
-            if let Ok(mut transform) = transformable_objects.get_mut(e) {
-                let angle = (rotated.pos_pressed - transform.translation.truncate())
-                    .angle_between(mouse.position - transform.translation.truncate());
-                *transform =
-                    transform.with_rotation(Quat::from_rotation_z(angle + rotated.base_rotation));
+        let center = rotated.center;
+        let angle = (rotated.pos_pressed - center).angle_between(mouse.position - center);
+        if angle.is_nan() {
+            return;
+        }
+        let rotation = Quat::from_rotation_z(angle);
+        for (e, before) in rotated.entities.iter() {
+            if let Ok((_, mut transform, _)) = query.get_mut(*e) {
+                let offset = rotation * (before.translation - center.extend(before.translation.z));
+                *transform = Transform {
+                    translation: center.extend(before.translation.z) + offset,
+                    rotation: rotation * before.rotation,
+                    scale: before.scale,
+                };
             }
         }
     }
@@ -225,9 +370,10 @@ pub fn update_origin(
                     let old = custom_shape.origin;
                     custom_shape.origin = -(Vec3::from(aabb.center)).truncate() + old;
                     *path = ShapePath::build_as(&custom_shape.clone());
-                    *transform = transform.with_translation(
-                        transform.translation - (custom_shape.origin - old).extend(0.0),
-                    );
+                    // The shift is in the space of the shape, which may be rotated and scaled.
+                    let shift = transform.rotation
+                        * (transform.scale * (custom_shape.origin - old).extend(0.0));
+                    *transform = transform.with_translation(transform.translation - shift);
                 }
             }
         }
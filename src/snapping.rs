@@ -0,0 +1,185 @@
+use crate::helpers::{shape_bounds, transformed_aabb, BoundsQuery};
+use crate::ShapeBase;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use bevy_prototype_lyon::prelude::{
+    shapes, DrawMode, GeometryBuilder, Path, RectangleOrigin, ShapePath, StrokeMode,
+};
+
+/// Snap distance in screen pixels, multiplied by the camera scale before use.
+pub const SNAP_DISTANCE: f32 = 6.0;
+
+pub struct SnappingPlugin;
+
+impl Plugin for SnappingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ObjectSnap>()
+            .init_resource::<ShapeBounds>()
+            .add_startup_system(spawn_guides)
+            .add_system_to_stage(CoreStage::PostUpdate, update_shape_bounds)
+            .add_system_to_stage(CoreStage::PostUpdate, draw_guides);
+    }
+}
+
+#[derive(Resource)]
+pub struct ObjectSnap {
+    pub enabled: bool,
+    guides: Vec<[Vec2; 2]>,
+}
+
+impl Default for ObjectSnap {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            guides: vec![],
+        }
+    }
+}
+
+/// World space bounds of every top-level shape, as of the end of the previous frame.
+#[derive(Resource, Default)]
+pub struct ShapeBounds(pub HashMap<Entity, Rect>);
+
+impl ShapeBounds {
+    pub fn union(&self, entities: &[Entity]) -> Option<Rect> {
+        entities
+            .iter()
+            .filter_map(|e| self.0.get(e))
+            .copied()
+            .reduce(|a, b| a.union(b))
+    }
+
+    pub fn others(&self, entities: &[Entity]) -> Vec<Rect> {
+        self.0
+            .iter()
+            .filter(|(e, _)| !entities.contains(e))
+            .map(|(_, r)| *r)
+            .collect()
+    }
+}
+
+#[derive(Component)]
+struct SnapGuides;
+
+fn nearest(values: &[f32], targets: &[f32], tolerance: f32) -> f32 {
+    values
+        .iter()
+        .flat_map(|v| targets.iter().map(move |t| t - v))
+        .filter(|d| d.abs() <= tolerance)
+        .min_by(|a, b| a.abs().total_cmp(&b.abs()))
+        .unwrap_or(0.0)
+}
+
+impl ObjectSnap {
+    /// Offset that aligns one of `xs` / `ys` with an edge or the center of a target,
+    /// remembering a guide line for every alignment of the snapped `moving` rectangle.
+    pub fn snap(
+        &mut self,
+        xs: &[f32],
+        ys: &[f32],
+        moving: Rect,
+        targets: &[Rect],
+        tolerance: f32,
+    ) -> Vec2 {
+        self.guides.clear();
+        if !self.enabled {
+            return Vec2::ZERO;
+        }
+        let target_xs = targets
+            .iter()
+            .flat_map(|r| [r.min.x, r.center().x, r.max.x])
+            .collect::<Vec<_>>();
+        let target_ys = targets
+            .iter()
+            .flat_map(|r| [r.min.y, r.center().y, r.max.y])
+            .collect::<Vec<_>>();
+        let offset = Vec2::new(
+            nearest(xs, &target_xs, tolerance),
+            nearest(ys, &target_ys, tolerance),
+        );
+        let moving = Rect {
+            min: moving.min + offset,
+            max: moving.max + offset,
+        };
+        for target in targets {
+            for x in xs.iter().map(|x| x + offset.x) {
+                if [target.min.x, target.center().x, target.max.x]
+                    .iter()
+                    .any(|t| (t - x).abs() < 0.01)
+                {
+                    self.guides.push([
+                        Vec2::new(x, moving.min.y.min(target.min.y)),
+                        Vec2::new(x, moving.max.y.max(target.max.y)),
+                    ]);
+                }
+            }
+            for y in ys.iter().map(|y| y + offset.y) {
+                if [target.min.y, target.center().y, target.max.y]
+                    .iter()
+                    .any(|t| (t - y).abs() < 0.01)
+                {
+                    self.guides.push([
+                        Vec2::new(moving.min.x.min(target.min.x), y),
+                        Vec2::new(moving.max.x.max(target.max.x), y),
+                    ]);
+                }
+            }
+        }
+        offset
+    }
+
+    pub fn clear(&mut self) {
+        self.guides.clear();
+    }
+}
+
+fn spawn_guides(mut commands: Commands) {
+    commands
+        .spawn(GeometryBuilder::build_as(
+            &shapes::Rectangle {
+                extents: Vec2::ZERO,
+                origin: RectangleOrigin::Center,
+            },
+            DrawMode::Stroke(StrokeMode::color(Color::FUCHSIA)),
+            Transform::from_translation(Vec3::new(0.0, 0.0, 180.0)),
+        ))
+        .insert(SnapGuides);
+}
+
+fn update_shape_bounds(
+    mut bounds: ResMut<ShapeBounds>,
+    meshes: Res<Assets<Mesh>>,
+    top_level: Query<Entity, (With<ShapeBase>, Without<Parent>)>,
+    shapes: BoundsQuery,
+) {
+    bounds.0 = top_level
+        .iter()
+        .filter_map(|e| {
+            let aabb = shape_bounds(e, &meshes, &shapes)?;
+            Some((e, transformed_aabb(&aabb, shapes.get(e).ok()?.2)))
+        })
+        .collect();
+}
+
+fn draw_guides(
+    snap: Res<ObjectSnap>,
+    mut drawn: Local<Vec<[Vec2; 2]>>,
+    projection: Query<&OrthographicProjection>,
+    mut guides: Query<(&mut Path, &mut DrawMode), With<SnapGuides>>,
+) {
+    if *drawn == snap.guides {
+        return;
+    }
+    *drawn = snap.guides.clone();
+    if let Ok((mut path, mut draw_mode)) = guides.get_single_mut() {
+        let scale = projection.get_single().map_or(1.0, |p| p.scale);
+        *path = snap
+            .guides
+            .iter()
+            .fold(ShapePath::new(), |path, [from, to]| {
+                path.add(&shapes::Line(*from, *to))
+            })
+            .build();
+        *draw_mode = DrawMode::Stroke(StrokeMode::new(Color::FUCHSIA, scale));
+    }
+}
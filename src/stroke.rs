@@ -0,0 +1,463 @@
+use crate::gradient::Gradient;
+use bevy::prelude::*;
+use bevy::render::mesh::{Indices, PrimitiveTopology};
+use bevy::sprite::Mesh2dHandle;
+use bevy_prototype_lyon::prelude::tess::math::{Point, Vector};
+use bevy_prototype_lyon::prelude::tess::path::iterator::PathIterator;
+use bevy_prototype_lyon::prelude::tess::path::path::Builder;
+use bevy_prototype_lyon::prelude::tess::path::{PathEvent, Winding};
+use bevy_prototype_lyon::prelude::tess::{
+    self, BuffersBuilder, FillTessellator, FillVertex, StrokeTessellator, StrokeVertex,
+    VertexBuffers,
+};
+use bevy_prototype_lyon::prelude::{DrawMode, FillOptions, Path};
+use serde::{Deserialize, Serialize};
+
+pub struct StrokePlugin;
+
+impl Plugin for StrokePlugin {
+    fn build(&self, app: &mut App) {
+        // Runs last so it replaces the mesh built by the shape plugin in the same frame.
+        app.add_system_to_stage(CoreStage::Last, build_strokes)
+            .add_system_to_stage(CoreStage::Last, reset_strokes::<Dashes>)
+            .add_system_to_stage(CoreStage::Last, reset_strokes::<Markers>)
+            .add_system_to_stage(CoreStage::Last, reset_strokes::<Gradient>);
+    }
+}
+
+/// Dash pattern of a shape's stroke, in the units of its path. Like in SVG, an odd number of
+/// lengths is repeated once, and `offset` is how far into the pattern each subpath starts.
+#[derive(Component, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Dashes {
+    pub lengths: Vec<f32>,
+    pub offset: f32,
+}
+
+impl Default for Dashes {
+    fn default() -> Self {
+        Self {
+            lengths: vec![10.0, 5.0],
+            offset: 0.0,
+        }
+    }
+}
+
+impl Dashes {
+    /// Shortest length of a dash or gap that can be entered. Patterns shorter than this look
+    /// solid, while splitting the stroke into countless pieces.
+    pub const MIN_LENGTH: f32 = 0.1;
+
+    /// Alternating dash and gap lengths, `None` when nothing would be drawn differently.
+    fn intervals(&self) -> Option<Vec<f32>> {
+        let mut intervals = self.lengths.iter().map(|l| l.max(0.0)).collect::<Vec<_>>();
+        if intervals.len() % 2 == 1 {
+            intervals.extend_from_within(..);
+        }
+        if intervals.iter().sum::<f32>() >= Dashes::MIN_LENGTH {
+            Some(intervals)
+        } else {
+            None
+        }
+    }
+}
+
+/// Decoration at one end of an open path, sized in stroke widths.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Marker {
+    None,
+    Arrow,
+    Circle,
+    Diamond,
+    Bar,
+}
+
+impl Default for Marker {
+    fn default() -> Self {
+        Self::None
+    }
+}
+
+/// Outline of a marker, in stroke widths with the end of the path at the origin and x pointing
+/// away from the path.
+pub enum MarkerShape {
+    Polygon(Vec<Vec2>),
+    Circle(f32),
+}
+
+/// How far any marker reaches from the end of its path, in stroke widths.
+pub const MARKER_REACH: f32 = 5.0;
+
+impl Marker {
+    pub const ALL: [(Marker, &'static str); 5] = [
+        (Marker::None, "None"),
+        (Marker::Arrow, "Arrow"),
+        (Marker::Circle, "Circle"),
+        (Marker::Diamond, "Diamond"),
+        (Marker::Bar, "Bar"),
+    ];
+
+    pub fn shape(&self) -> Option<MarkerShape> {
+        let polygon = |points: &[(f32, f32)]| {
+            MarkerShape::Polygon(points.iter().map(|(x, y)| Vec2::new(*x, *y)).collect())
+        };
+        match self {
+            Marker::None => None,
+            // The tip sticks out past the end so that the stroke doesn't poke through it.
+            Marker::Arrow => Some(polygon(&[(2.0, 0.0), (-4.0, 3.0), (-4.0, -3.0)])),
+            Marker::Circle => Some(MarkerShape::Circle(2.5)),
+            Marker::Diamond => Some(polygon(&[(3.0, 0.0), (0.0, 3.0), (-3.0, 0.0), (0.0, -3.0)])),
+            Marker::Bar => Some(polygon(&[
+                (0.5, -3.0),
+                (0.5, 3.0),
+                (-0.5, 3.0),
+                (-0.5, -3.0),
+            ])),
+        }
+    }
+}
+
+/// Markers drawn at the start and end of a shape's first contour, as long as it is open.
+#[derive(Component, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Markers {
+    pub start: Marker,
+    pub end: Marker,
+}
+
+/// Where both ends of the first contour are and which way they point, `None` when it's closed.
+fn path_ends(path: &tess::path::Path) -> Option<[(Point, Vector); 2]> {
+    let (mut start, mut end) = (None, None);
+    for event in path.iter() {
+        let points = match event {
+            PathEvent::Begin { .. } => continue,
+            PathEvent::Line { from, to } => vec![from, to],
+            PathEvent::Quadratic { from, ctrl, to } => vec![from, ctrl, to],
+            PathEvent::Cubic {
+                from,
+                ctrl1,
+                ctrl2,
+                to,
+            } => vec![from, ctrl1, ctrl2, to],
+            PathEvent::End { close: true, .. } => return None,
+            PathEvent::End { .. } => break,
+        };
+        // Control points on top of an end don't give a direction, the next ones do.
+        let (from, to) = (points[0], points[points.len() - 1]);
+        if start.is_none() {
+            start = points
+                .iter()
+                .find(|p| **p != from)
+                .map(|p| (from, from - *p));
+        }
+        if let Some(p) = points.iter().rev().find(|p| **p != to) {
+            end = Some((to, to - *p));
+        }
+    }
+    Some([start?, end?])
+}
+
+fn add_marker(builder: &mut Builder, marker: Marker, (at, direction): (Point, Vector), width: f32) {
+    let x = direction.normalize() * width;
+    let y = Vector::new(-x.y, x.x);
+    match marker.shape() {
+        Some(MarkerShape::Polygon(points)) => {
+            let place = |p: &Vec2| at + x * p.x + y * p.y;
+            builder.begin(place(&points[0]));
+            for p in points[1..].iter() {
+                builder.line_to(place(p));
+            }
+            builder.end(true);
+        }
+        Some(MarkerShape::Circle(radius)) => {
+            builder.add_circle(at, radius * width, Winding::Positive)
+        }
+        None => {}
+    }
+}
+
+struct Dasher<'a> {
+    intervals: &'a [f32],
+    index: usize,
+    remaining: f32,
+    drawing: bool,
+}
+
+impl<'a> Dasher<'a> {
+    fn begin(intervals: &'a [f32], offset: f32, at: Point, builder: &mut Builder) -> Self {
+        let mut phase = offset.rem_euclid(intervals.iter().sum());
+        let mut index = 0;
+        while phase >= intervals[index] {
+            phase -= intervals[index];
+            index = (index + 1) % intervals.len();
+        }
+        let drawing = index % 2 == 0;
+        if drawing {
+            builder.begin(at);
+        }
+        Self {
+            intervals,
+            index,
+            remaining: intervals[index] - phase,
+            drawing,
+        }
+    }
+
+    fn line(&mut self, from: Point, to: Point, builder: &mut Builder) {
+        let length = (to - from).length();
+        let mut travelled = 0.0;
+        while length - travelled > self.remaining {
+            travelled += self.remaining;
+            let at = from.lerp(to, travelled / length);
+            if self.drawing {
+                builder.line_to(at);
+                builder.end(false);
+            } else {
+                builder.begin(at);
+            }
+            self.drawing = !self.drawing;
+            self.index = (self.index + 1) % self.intervals.len();
+            self.remaining = self.intervals[self.index];
+        }
+        self.remaining -= length - travelled;
+        if self.drawing {
+            builder.line_to(to);
+        }
+    }
+
+    fn end(self, builder: &mut Builder) {
+        if self.drawing {
+            builder.end(false);
+        }
+    }
+}
+
+/// Splits `path` into the dashes of its stroke, the pattern restarts with each subpath.
+pub fn dash_path(path: &tess::path::Path, dashes: &Dashes, tolerance: f32) -> tess::path::Path {
+    let intervals = match dashes.intervals() {
+        Some(intervals) => intervals,
+        None => return path.clone(),
+    };
+    let mut builder = Builder::new();
+    let mut dasher = None;
+    for event in path.iter().flattened(tolerance) {
+        match event {
+            PathEvent::Begin { at } => {
+                dasher = Some(Dasher::begin(&intervals, dashes.offset, at, &mut builder));
+            }
+            PathEvent::Line { from, to } => {
+                if let Some(dasher) = &mut dasher {
+                    dasher.line(from, to, &mut builder);
+                }
+            }
+            PathEvent::End { last, first, close } => {
+                if let Some(mut dasher) = dasher.take() {
+                    if close {
+                        dasher.line(last, first, &mut builder);
+                    }
+                    dasher.end(&mut builder);
+                }
+            }
+            _ => {}
+        }
+    }
+    builder.build()
+}
+
+#[derive(Clone, Copy)]
+struct Vertex {
+    position: [f32; 2],
+    color: [f32; 4],
+    /// Tells the gradient shader the fill from the stroke, which may have the same color.
+    fill: bool,
+}
+
+type StrokeQuery<'a> = (
+    &'a Path,
+    &'a DrawMode,
+    Option<&'a Dashes>,
+    Option<&'a Markers>,
+    Option<&'a Gradient>,
+    &'a mut Mesh2dHandle,
+);
+
+/// Builds the mesh of dashed, marked or gradient filled shapes the way the shape plugin does,
+/// with a dashed stroke, the markers filled in the stroke color and the fill tagged in the first
+/// texture coordinate.
+fn build_strokes(
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut shapes: Query<
+        StrokeQuery,
+        Or<(
+            Changed<Path>,
+            Changed<DrawMode>,
+            Changed<Dashes>,
+            Changed<Markers>,
+            Added<Gradient>,
+        )>,
+    >,
+) {
+    for (path, draw_mode, dashes, markers, gradient, mut mesh) in shapes.iter_mut() {
+        if dashes.is_none() && markers.is_none() && gradient.is_none() {
+            continue;
+        }
+        let (fill_mode, stroke_mode) = match draw_mode {
+            DrawMode::Fill(_) => continue,
+            DrawMode::Stroke(stroke_mode) => (None, stroke_mode),
+            DrawMode::Outlined {
+                fill_mode,
+                outline_mode,
+            } => (Some(fill_mode), outline_mode),
+        };
+        let mut buffers: VertexBuffers<Vertex, u32> = VertexBuffers::new();
+        if let Some(fill_mode) = fill_mode {
+            let color = fill_mode.color.as_linear_rgba_f32();
+            let result = FillTessellator::new().tessellate_path(
+                &path.0,
+                &fill_mode.options,
+                &mut BuffersBuilder::new(&mut buffers, |v: FillVertex| Vertex {
+                    position: v.position().to_array(),
+                    color,
+                    fill: true,
+                }),
+            );
+            if let Err(e) = result {
+                warn!("failed to tessellate the fill of a shape: {:?}", e);
+            }
+        }
+        let tolerance = stroke_mode.options.tolerance;
+        let stroke = match dashes {
+            Some(dashes) => dash_path(&path.0, dashes, tolerance),
+            None => path.0.clone(),
+        };
+        let color = stroke_mode.color.as_linear_rgba_f32();
+        let result = StrokeTessellator::new().tessellate_path(
+            &stroke,
+            &stroke_mode.options,
+            &mut BuffersBuilder::new(&mut buffers, |v: StrokeVertex| Vertex {
+                position: v.position().to_array(),
+                color,
+                fill: false,
+            }),
+        );
+        if let Err(e) = result {
+            warn!("failed to tessellate a stroke: {:?}", e);
+        }
+        if let (Some(markers), Some([start, end])) = (markers, path_ends(&path.0)) {
+            let mut builder = Builder::new();
+            let width = stroke_mode.options.line_width;
+            add_marker(&mut builder, markers.start, start, width);
+            add_marker(&mut builder, markers.end, end, width);
+            let result = FillTessellator::new().tessellate_path(
+                &builder.build(),
+                &FillOptions::tolerance(tolerance),
+                &mut BuffersBuilder::new(&mut buffers, |v: FillVertex| Vertex {
+                    position: v.position().to_array(),
+                    color,
+                    fill: false,
+                }),
+            );
+            if let Err(e) = result {
+                warn!("failed to tessellate the markers of a stroke: {:?}", e);
+            }
+        }
+        let mut stroked_mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        stroked_mesh.set_indices(Some(Indices::U32(buffers.indices)));
+        stroked_mesh.insert_attribute(
+            Mesh::ATTRIBUTE_POSITION,
+            buffers
+                .vertices
+                .iter()
+                .map(|v| [v.position[0], v.position[1], 0.0])
+                .collect::<Vec<_>>(),
+        );
+        stroked_mesh.insert_attribute(
+            Mesh::ATTRIBUTE_COLOR,
+            buffers.vertices.iter().map(|v| v.color).collect::<Vec<_>>(),
+        );
+        stroked_mesh.insert_attribute(
+            Mesh::ATTRIBUTE_UV_0,
+            buffers
+                .vertices
+                .iter()
+                .map(|v| [if v.fill { 1.0 } else { 0.0 }, 0.0])
+                .collect::<Vec<_>>(),
+        );
+        *mesh = Mesh2dHandle(meshes.add(stroked_mesh));
+    }
+}
+
+/// Lets the shape plugin rebuild the plain mesh once the dashes, markers or gradient are removed.
+fn reset_strokes<T: Component>(removed: RemovedComponents<T>, mut shapes: Query<&mut DrawMode>) {
+    for e in removed.iter() {
+        if let Ok(mut draw_mode) = shapes.get_mut(e) {
+            draw_mode.set_changed();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Horizontal lines from `0` to each of `lengths`, stacked above each other.
+    fn lines(lengths: &[f32]) -> tess::path::Path {
+        let mut builder = Builder::new();
+        for (i, length) in lengths.iter().enumerate() {
+            builder.begin(Point::new(0.0, i as f32));
+            builder.line_to(Point::new(*length, i as f32));
+            builder.end(false);
+        }
+        builder.build()
+    }
+
+    /// Start and end of every dash along the x axis, rounded to whole units.
+    fn dash_ends(lengths: Vec<f32>, offset: f32, path: &tess::path::Path) -> Vec<(i32, i32)> {
+        let dashed = dash_path(path, &Dashes { lengths, offset }, 0.1);
+        let mut ends = vec![];
+        let mut start = 0.0;
+        for event in dashed.iter() {
+            match event {
+                PathEvent::Begin { at } => start = at.x,
+                PathEvent::End { last, .. } => {
+                    ends.push((start.round() as i32, last.x.round() as i32))
+                }
+                _ => {}
+            }
+        }
+        ends
+    }
+
+    #[test]
+    fn dashes_alternate_with_gaps() {
+        let path = lines(&[30.0]);
+        assert_eq!(dash_ends(vec![10.0, 5.0], 0.0, &path), [(0, 10), (15, 25)]);
+    }
+
+    #[test]
+    fn odd_patterns_are_repeated() {
+        let path = lines(&[30.0]);
+        assert_eq!(dash_ends(vec![10.0], 0.0, &path), [(0, 10), (20, 30)]);
+    }
+
+    #[test]
+    fn offsets_wrap_around_the_pattern() {
+        let path = lines(&[30.0]);
+        let expected = [(0, 5), (10, 20), (25, 30)];
+        assert_eq!(dash_ends(vec![10.0, 5.0], 5.0, &path), expected);
+        assert_eq!(dash_ends(vec![10.0, 5.0], 35.0, &path), expected);
+        assert_eq!(dash_ends(vec![10.0, 5.0], -10.0, &path), expected);
+    }
+
+    #[test]
+    fn each_subpath_restarts_the_pattern() {
+        let path = lines(&[12.0, 12.0]);
+        assert_eq!(dash_ends(vec![10.0, 5.0], 0.0, &path), [(0, 10), (0, 10)]);
+    }
+
+    #[test]
+    fn empty_patterns_leave_the_stroke_solid() {
+        let path = lines(&[30.0]);
+        assert_eq!(dash_ends(vec![], 0.0, &path), [(0, 30)]);
+        assert_eq!(dash_ends(vec![0.0, 0.0], 3.0, &path), [(0, 30)]);
+        assert_eq!(dash_ends(vec![0.01, 0.01], 0.0, &path), [(0, 30)]);
+    }
+}
@@ -0,0 +1,584 @@
+use crate::custom_shape::{CustomShapeRaw, ShapeSegment};
+use crate::document::{
+    CapRecord, FillRuleRecord, GeometryRecord, JoinRecord, ShapeRecord, ShapeRecordQuery,
+    StyleRecord,
+};
+use crate::gradient::{Gradient, GradientKind};
+use crate::helpers::ctrl_pressed;
+use crate::stroke::{Dashes, MarkerShape, Markers, MARKER_REACH};
+use crate::{Moving, OrderedShapes, PrimitiveShape, PrimitiveType};
+use bevy::prelude::*;
+use bevy_prototype_lyon::prelude::tess::math::Point;
+use std::fmt::Write;
+use std::fs;
+
+pub const SVG_PATH: &str = "drawing.svg";
+
+pub struct SvgExportPlugin;
+
+impl Plugin for SvgExportPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(handle_export_input);
+    }
+}
+
+fn handle_export_input(
+    input: Res<Input<KeyCode>>,
+    ordered: Res<OrderedShapes>,
+    shapes: Query<ShapeRecordQuery, Without<Moving>>,
+) {
+    if ctrl_pressed(&input) && input.just_pressed(KeyCode::E) {
+        let records = ordered
+            .0
+            .iter()
+            .filter_map(|e| ShapeRecord::capture(*e, &shapes))
+            .collect::<Vec<ShapeRecord>>();
+        match fs::write(SVG_PATH, shapes_to_svg(&records)) {
+            Ok(()) => info!("exported {} shapes to {}", records.len(), SVG_PATH),
+            Err(e) => error!("failed to export {}: {}", SVG_PATH, e),
+        }
+    }
+}
+
+/// Hidden shapes are left out of the export.
+pub fn shapes_to_svg(records: &[ShapeRecord]) -> String {
+    let records = records.iter().filter(|r| !r.hidden).collect::<Vec<_>>();
+    let bounds = records
+        .iter()
+        .filter_map(|r| record_bounds(r))
+        .reduce(|a, b| a.union(b));
+    let mut svg = String::new();
+    match bounds {
+        Some(bounds) => {
+            let _ = writeln!(
+                svg,
+                r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="{} {} {} {}" width="{}" height="{}">"#,
+                bounds.min.x,
+                -bounds.max.y,
+                bounds.width(),
+                bounds.height(),
+                bounds.width(),
+                bounds.height()
+            );
+        }
+        // A viewBox without area wouldn't be rendered at all.
+        None => svg.push_str("<svg xmlns=\"http://www.w3.org/2000/svg\">\n"),
+    }
+    svg.push_str("<g transform=\"scale(1 -1)\">\n");
+    let mut ids = 0;
+    for record in records {
+        svg.push_str(&shape_element(record, &mut ids));
+        svg.push('\n');
+    }
+    svg.push_str("</g>\n</svg>\n");
+    svg
+}
+
+/// `ids` counts the gradients and markers written so far, to give each one a unique id.
+pub fn shape_element(record: &ShapeRecord, ids: &mut usize) -> String {
+    let mut defs = String::new();
+    let fill = record.gradient.as_ref().map(|gradient| {
+        *ids += 1;
+        let id = format!("gradient{}", ids);
+        defs.push_str(&gradient_element(gradient, &id));
+        format!("url(#{})", id)
+    });
+    let mut attributes = match &record.style {
+        Some(style) => format!(
+            r#"transform="{}" {}"#,
+            svg_transform(record),
+            style_attributes(style, fill.as_deref(), record.dashes.as_ref())
+        ),
+        None => format!(r#"transform="{}""#, svg_transform(record)),
+    };
+    // Like on the canvas, only open paths get markers.
+    if let (
+        Some(markers),
+        Some(
+            StyleRecord::Stroke(stroke)
+            | StyleRecord::Outlined {
+                outline: stroke, ..
+            },
+        ),
+        GeometryRecord::Custom(CustomShapeRaw { closed: false, .. }),
+    ) = (record.markers, record.style, &record.geometry)
+    {
+        for (marker, attribute) in [(markers.start, "marker-start"), (markers.end, "marker-end")] {
+            if let Some(shape) = marker.shape() {
+                *ids += 1;
+                let id = format!("marker{}", ids);
+                defs.push_str(&marker_element(&shape, &id, stroke.color));
+                let _ = write!(attributes, r#" {}="url(#{})""#, attribute, id);
+            }
+        }
+    }
+    if !defs.is_empty() {
+        defs = format!("<defs>{}</defs>\n", defs);
+    }
+    let element = match &record.geometry {
+        GeometryRecord::Group(children) => {
+            let mut group = format!("<g {}>\n", attributes);
+            for child in children.iter().filter(|c| !c.hidden) {
+                group.push_str(&shape_element(child, ids));
+                group.push('\n');
+            }
+            group.push_str("</g>");
+            group
+        }
+        GeometryRecord::Custom(custom) => {
+            format!(r#"<path d="{}" {}/>"#, path_data(custom), attributes)
+        }
+        GeometryRecord::Primitive(primitive) => primitive_element(primitive, &attributes),
+    };
+    defs + &element
+}
+
+/// Gradient coordinates are relative to the bounds of the shape, like on the canvas.
+fn gradient_element(gradient: &Gradient, id: &str) -> String {
+    let (start, end) = (gradient.start, gradient.end);
+    let (mut element, tag) = match gradient.kind {
+        GradientKind::Linear => (
+            format!(
+                r#"<linearGradient id="{}" gradientUnits="objectBoundingBox" x1="{}" y1="{}" x2="{}" y2="{}">"#,
+                id, start.x, start.y, end.x, end.y
+            ),
+            "linearGradient",
+        ),
+        GradientKind::Radial => (
+            format!(
+                r#"<radialGradient id="{}" gradientUnits="objectBoundingBox" cx="{}" cy="{}" r="{}">"#,
+                id,
+                start.x,
+                start.y,
+                start.distance(end)
+            ),
+            "radialGradient",
+        ),
+    };
+    for stop in gradient.stops.iter() {
+        let (color, opacity) = svg_color(stop.color);
+        let _ = write!(
+            element,
+            r#"<stop offset="{}" stop-color="{}" stop-opacity="{}"/>"#,
+            stop.offset, color, opacity
+        );
+    }
+    let _ = write!(element, "</{}>", tag);
+    element
+}
+
+/// Drawn in stroke widths, `auto-start-reverse` turns it away from the path at both ends.
+fn marker_element(shape: &MarkerShape, id: &str, color: Color) -> String {
+    let (color, opacity) = svg_color(color);
+    let content = match shape {
+        MarkerShape::Polygon(points) => {
+            let points = points
+                .iter()
+                .map(|p| format!("{},{}", p.x, p.y))
+                .collect::<Vec<_>>();
+            format!(r#"<polygon points="{}"/>"#, points.join(" "))
+        }
+        MarkerShape::Circle(radius) => format!(r#"<circle r="{}"/>"#, radius),
+    };
+    format!(
+        r#"<marker id="{}" markerUnits="strokeWidth" orient="auto-start-reverse" overflow="visible" fill="{}" fill-opacity="{}">{}</marker>"#,
+        id, color, opacity, content
+    )
+}
+
+pub fn path_data(custom: &CustomShapeRaw) -> String {
+    let o = custom.origin;
+    let mut d = String::new();
+    for (start, segments, closed) in custom.contours() {
+        if !d.is_empty() {
+            d.push(' ');
+        }
+        contour_data(&mut d, o, start, segments, closed);
+    }
+    d
+}
+
+fn contour_data(d: &mut String, o: Vec2, start: Point, segments: &[ShapeSegment], closed: bool) {
+    let _ = write!(d, "M {} {}", start.x + o.x, start.y + o.y);
+    for segment in segments.iter() {
+        let _ = match *segment {
+            ShapeSegment::Line(to) => write!(d, " L {} {}", to.x + o.x, to.y + o.y),
+            ShapeSegment::QuadraticBezier { ctrl, to } => write!(
+                d,
+                " Q {} {} {} {}",
+                ctrl.x + o.x,
+                ctrl.y + o.y,
+                to.x + o.x,
+                to.y + o.y
+            ),
+            ShapeSegment::CubicBezier { ctrl, ctrl2, to } => write!(
+                d,
+                " C {} {} {} {} {} {}",
+                ctrl.x + o.x,
+                ctrl.y + o.y,
+                ctrl2.x + o.x,
+                ctrl2.y + o.y,
+                to.x + o.x,
+                to.y + o.y
+            ),
+        };
+    }
+    if closed {
+        d.push_str(" Z");
+    }
+}
+
+fn primitive_element(primitive: &PrimitiveShape, attributes: &str) -> String {
+    let size = primitive.extents;
+    match primitive.shape {
+        PrimitiveType::Rectangle => format!(
+            r#"<rect x="{}" y="{}" width="{}" height="{}" {}/>"#,
+            -size.x / 2.0,
+            -size.y / 2.0,
+            size.x,
+            size.y,
+            attributes
+        ),
+        PrimitiveType::Ellipse => format!(
+            r#"<ellipse cx="0" cy="0" rx="{}" ry="{}" {}/>"#,
+            size.x / 2.0,
+            size.y / 2.0,
+            attributes
+        ),
+        PrimitiveType::RoundedRectangle => {
+            let [tl, tr, br, bl] = primitive.clamped_corner_radii();
+            if tl == tr && tr == br && br == bl {
+                format!(
+                    r#"<rect x="{}" y="{}" width="{}" height="{}" rx="{}" {}/>"#,
+                    -size.x / 2.0,
+                    -size.y / 2.0,
+                    size.x,
+                    size.y,
+                    tl,
+                    attributes
+                )
+            } else {
+                let (w, h) = (size.x / 2.0, size.y / 2.0);
+                // Counterclockwise in the y-up space of the shape, starting on the bottom edge.
+                let mut d = format!("M {} {}", -w + bl, -h);
+                for (r, line_to, arc_to) in [
+                    (br, Vec2::new(w - br, -h), Vec2::new(w, -h + br)),
+                    (tr, Vec2::new(w, h - tr), Vec2::new(w - tr, h)),
+                    (tl, Vec2::new(-w + tl, h), Vec2::new(-w, h - tl)),
+                    (bl, Vec2::new(-w, -h + bl), Vec2::new(-w + bl, -h)),
+                ] {
+                    let _ = write!(
+                        d,
+                        " L {} {} A {} {} 0 0 1 {} {}",
+                        line_to.x, line_to.y, r, r, arc_to.x, arc_to.y
+                    );
+                }
+                format!(r#"<path d="{} Z" {}/>"#, d, attributes)
+            }
+        }
+        PrimitiveType::Polygon { .. } | PrimitiveType::Star { .. } => {
+            let points = primitive
+                .polygon_points()
+                .iter()
+                .map(|v| format!("{},{}", v.x, v.y))
+                .collect::<Vec<_>>();
+            format!(r#"<polygon points="{}" {}/>"#, points.join(" "), attributes)
+        }
+    }
+}
+
+fn svg_transform(record: &ShapeRecord) -> String {
+    let (axis, angle) = record.rotation.to_axis_angle();
+    format!(
+        "translate({} {}) rotate({}) scale({} {})",
+        record.translation.x,
+        record.translation.y,
+        (axis.z * angle).to_degrees(),
+        record.scale.x,
+        record.scale.y
+    )
+}
+
+pub fn svg_color(color: Color) -> (String, f32) {
+    let [r, g, b, a] = color.as_rgba_f32();
+    (
+        format!(
+            "#{:02x}{:02x}{:02x}",
+            (r * 255.0).round() as u8,
+            (g * 255.0).round() as u8,
+            (b * 255.0).round() as u8
+        ),
+        a,
+    )
+}
+
+/// `fill_paint` replaces the fill color, with a reference to a gradient.
+fn style_attributes(
+    style: &StyleRecord,
+    fill_paint: Option<&str>,
+    dashes: Option<&Dashes>,
+) -> String {
+    let (fill, stroke) = match style {
+        StyleRecord::Fill(fill) => (Some(fill), None),
+        StyleRecord::Stroke(stroke) => (None, Some(stroke)),
+        StyleRecord::Outlined { fill, outline } => (Some(fill), Some(outline)),
+    };
+    let mut attributes = String::new();
+    match (fill, fill_paint) {
+        (Some(fill), Some(paint)) => {
+            let _ = write!(
+                attributes,
+                r#"fill="{}" fill-rule="{}""#,
+                paint,
+                fill_rule(fill.rule)
+            );
+        }
+        (Some(fill), None) => {
+            let (color, opacity) = svg_color(fill.color);
+            let _ = write!(
+                attributes,
+                r#"fill="{}" fill-opacity="{}" fill-rule="{}""#,
+                color,
+                opacity,
+                fill_rule(fill.rule)
+            );
+        }
+        (None, _) => attributes.push_str(r#"fill="none""#),
+    }
+    match stroke {
+        Some(stroke) => {
+            let (color, opacity) = svg_color(stroke.color);
+            let _ = write!(
+                attributes,
+                r#" stroke="{}" stroke-opacity="{}" stroke-width="{}" stroke-linecap="{}" stroke-linejoin="{}" stroke-miterlimit="{}""#,
+                color,
+                opacity,
+                stroke.width,
+                match stroke.cap {
+                    CapRecord::Butt => "butt",
+                    CapRecord::Square => "square",
+                    CapRecord::Round => "round",
+                },
+                match stroke.join {
+                    JoinRecord::Miter => "miter",
+                    JoinRecord::Round => "round",
+                    JoinRecord::Bevel => "bevel",
+                },
+                stroke.miter_limit
+            );
+            if let Some(dashes) = dashes.filter(|d| !d.lengths.is_empty()) {
+                let lengths = dashes
+                    .lengths
+                    .iter()
+                    .map(|l| l.to_string())
+                    .collect::<Vec<_>>();
+                let _ = write!(
+                    attributes,
+                    r#" stroke-dasharray="{}" stroke-dashoffset="{}""#,
+                    lengths.join(" "),
+                    dashes.offset
+                );
+            }
+        }
+        None => attributes.push_str(r#" stroke="none""#),
+    }
+    attributes
+}
+
+fn fill_rule(rule: FillRuleRecord) -> &'static str {
+    match rule {
+        FillRuleRecord::EvenOdd => "evenodd",
+        FillRuleRecord::NonZero => "nonzero",
+    }
+}
+
+/// `None` for groups without visible shapes.
+fn record_bounds(record: &ShapeRecord) -> Option<Rect> {
+    let local = match &record.geometry {
+        GeometryRecord::Custom(custom) => {
+            let mut points = vec![];
+            for (start, _, _) in custom.contours() {
+                points.push(Vec2::new(start.x, start.y));
+            }
+            for segment in custom.contours().flat_map(|(_, segments, _)| segments) {
+                match *segment {
+                    ShapeSegment::Line(to) => points.push(Vec2::new(to.x, to.y)),
+                    ShapeSegment::QuadraticBezier { ctrl, to } => {
+                        points.push(Vec2::new(ctrl.x, ctrl.y));
+                        points.push(Vec2::new(to.x, to.y));
+                    }
+                    ShapeSegment::CubicBezier { ctrl, ctrl2, to } => {
+                        points.push(Vec2::new(ctrl.x, ctrl.y));
+                        points.push(Vec2::new(ctrl2.x, ctrl2.y));
+                        points.push(Vec2::new(to.x, to.y));
+                    }
+                }
+            }
+            points.iter().map(|p| *p + custom.origin).collect()
+        }
+        GeometryRecord::Primitive(primitive) => {
+            let half = primitive.extents / 2.0;
+            vec![
+                Vec2::new(-half.x, -half.y),
+                Vec2::new(half.x, -half.y),
+                Vec2::new(half.x, half.y),
+                Vec2::new(-half.x, half.y),
+            ]
+        }
+        GeometryRecord::Group(children) => {
+            let inner = children
+                .iter()
+                .filter(|c| !c.hidden)
+                .filter_map(record_bounds)
+                .reduce(|a, b| a.union(b))?;
+            vec![
+                inner.min,
+                Vec2::new(inner.max.x, inner.min.y),
+                inner.max,
+                Vec2::new(inner.min.x, inner.max.y),
+            ]
+        }
+    };
+    let stroke_width = match record.style {
+        Some(StyleRecord::Stroke(stroke))
+        | Some(StyleRecord::Outlined {
+            outline: stroke, ..
+        }) => stroke.width,
+        _ => 0.0,
+    };
+    // Markers reach further out than the stroke itself.
+    let reach = match record.markers {
+        Some(markers) if markers != Markers::default() => MARKER_REACH,
+        _ => 0.5,
+    };
+    let transform = record.transform();
+    local
+        .iter()
+        .map(|p| transform.transform_point(p.extend(0.0)).truncate())
+        .fold(None, |bounds: Option<Rect>, p| {
+            Some(match bounds {
+                Some(b) => b.union_point(p),
+                None => Rect::from_center_size(p, Vec2::ZERO),
+            })
+        })
+        .map(|bounds| bounds.inset(stroke_width * reach))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::custom_shape::Subpath;
+    use crate::document::FillRecord;
+
+    fn record(geometry: GeometryRecord) -> ShapeRecord {
+        ShapeRecord {
+            name: None,
+            translation: Vec3::ZERO,
+            rotation: Quat::IDENTITY,
+            scale: Vec3::ONE,
+            style: Some(StyleRecord::Fill(FillRecord {
+                color: Color::BLACK,
+                rule: FillRuleRecord::NonZero,
+            })),
+            gradient: None,
+            dashes: None,
+            markers: None,
+            connector: None,
+            geometry,
+            locked: false,
+            hidden: false,
+        }
+    }
+
+    fn rectangle(width: f32, height: f32) -> GeometryRecord {
+        GeometryRecord::Primitive(PrimitiveShape {
+            shape: PrimitiveType::Rectangle,
+            extents: Vec2::new(width, height),
+            corner_radii: [0.0; 4],
+        })
+    }
+
+    #[test]
+    fn empty_export_has_no_view_box() {
+        let svg = shapes_to_svg(&[]);
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\">\n"));
+        assert!(!svg.contains("viewBox"));
+    }
+
+    #[test]
+    fn hidden_shapes_are_left_out() {
+        let mut hidden = record(rectangle(10.0, 10.0));
+        hidden.hidden = true;
+        let svg = shapes_to_svg(&[hidden]);
+        assert!(!svg.contains("viewBox"));
+        assert!(!svg.contains("<rect"));
+    }
+
+    #[test]
+    fn view_box_fits_the_shapes() {
+        let mut shape = record(rectangle(20.0, 10.0));
+        shape.translation = Vec3::new(5.0, 0.0, 0.0);
+        let svg = shapes_to_svg(&[shape]);
+        assert!(
+            svg.contains(r#"viewBox="-5 -5 20 10" width="20" height="10""#),
+            "{}",
+            svg
+        );
+    }
+
+    #[test]
+    fn empty_groups_leave_the_bounds_alone() {
+        let mut shape = record(rectangle(10.0, 10.0));
+        shape.translation = Vec3::new(20.0, 20.0, 0.0);
+        let mut group = record(GeometryRecord::Group(vec![]));
+        group.style = None;
+        let svg = shapes_to_svg(&[shape, group]);
+        // The y axis is flipped, so the top of the shape becomes the top of the viewBox.
+        assert!(svg.contains(r#"viewBox="15 -25 10 10""#), "{}", svg);
+    }
+
+    #[test]
+    fn path_data_is_offset_by_the_origin() {
+        let shape = CustomShapeRaw {
+            segments: vec![
+                ShapeSegment::Line(Point::new(10.0, 0.0)),
+                ShapeSegment::QuadraticBezier {
+                    ctrl: Point::new(10.0, 10.0),
+                    to: Point::new(0.0, 10.0),
+                },
+            ],
+            closed: true,
+            origin: Vec2::new(-5.0, -5.0),
+            subpaths: vec![],
+        };
+        assert_eq!(path_data(&shape), "M -5 -5 L 5 -5 Q 5 5 -5 5 Z");
+    }
+
+    #[test]
+    fn subpaths_start_contours_of_their_own() {
+        let shape = CustomShapeRaw {
+            segments: vec![
+                ShapeSegment::Line(Point::new(10.0, 0.0)),
+                ShapeSegment::Line(Point::new(10.0, 10.0)),
+            ],
+            closed: true,
+            origin: Vec2::new(1.0, 1.0),
+            subpaths: vec![
+                Subpath {
+                    start: Point::new(2.0, 2.0),
+                    segments: vec![ShapeSegment::Line(Point::new(4.0, 2.0))],
+                    closed: true,
+                },
+                Subpath {
+                    start: Point::new(5.0, 5.0),
+                    segments: vec![ShapeSegment::Line(Point::new(6.0, 6.0))],
+                    closed: false,
+                },
+            ],
+        };
+        assert_eq!(
+            path_data(&shape),
+            "M 1 1 L 11 1 L 11 11 Z M 3 3 L 5 3 Z M 6 6 L 7 7"
+        );
+    }
+}
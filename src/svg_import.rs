@@ -0,0 +1,523 @@
+use crate::custom_shape::{CustomShapeRaw, ShapeSegment, Subpath};
+use crate::document::{
+    spawn_shape, CapRecord, FillRecord, FillRuleRecord, GeometryRecord, JoinRecord, ShapeRecord,
+    StrokeRecord, StyleRecord,
+};
+use crate::helpers::{ctrl_pressed, point_from_positions};
+use crate::history::{EditAction, HistoryEvent};
+use crate::svg_export::SVG_PATH;
+use crate::{OrderedShapes, PrimitiveShape, PrimitiveType};
+use bevy::math::Affine2;
+use bevy::prelude::*;
+use bevy::window::FileDragAndDrop;
+use bevy_prototype_lyon::prelude::StrokeOptions;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use svgtypes::{Length, Paint, PointsParser, SimplePathSegment, SimplifyingPathParser};
+
+pub struct SvgImportPlugin;
+
+impl Plugin for SvgImportPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(handle_import_input);
+    }
+}
+
+#[derive(Debug)]
+pub enum SvgImportError {
+    Io(std::io::Error),
+    Xml(roxmltree::Error),
+}
+
+impl Display for SvgImportError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SvgImportError::Io(e) => write!(f, "{}", e),
+            SvgImportError::Xml(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<std::io::Error> for SvgImportError {
+    fn from(e: std::io::Error) -> Self {
+        SvgImportError::Io(e)
+    }
+}
+
+impl From<roxmltree::Error> for SvgImportError {
+    fn from(e: roxmltree::Error) -> Self {
+        SvgImportError::Xml(e)
+    }
+}
+
+#[derive(Clone, Copy)]
+struct SvgStyle {
+    fill: Option<Color>,
+    fill_rule: FillRuleRecord,
+    stroke: Option<Color>,
+    stroke_width: f32,
+    cap: CapRecord,
+    join: JoinRecord,
+    miter_limit: f32,
+    fill_opacity: f32,
+    stroke_opacity: f32,
+    opacity: f32,
+}
+
+impl Default for SvgStyle {
+    fn default() -> Self {
+        Self {
+            fill: Some(Color::BLACK),
+            fill_rule: FillRuleRecord::NonZero,
+            stroke: None,
+            stroke_width: 1.0,
+            cap: CapRecord::Butt,
+            join: JoinRecord::Miter,
+            miter_limit: 4.0,
+            fill_opacity: 1.0,
+            stroke_opacity: 1.0,
+            opacity: 1.0,
+        }
+    }
+}
+
+struct Contour {
+    start: Vec2,
+    segments: Vec<ShapeSegment>,
+    points: Vec<Vec2>,
+    closed: bool,
+}
+
+fn handle_import_input(
+    mut commands: Commands,
+    input: Res<Input<KeyCode>>,
+    mut dropped: EventReader<FileDragAndDrop>,
+    mut ordered: ResMut<OrderedShapes>,
+    mut history: EventWriter<HistoryEvent>,
+) {
+    let mut paths = dropped
+        .iter()
+        .filter_map(|event| match event {
+            FileDragAndDrop::DroppedFile { path_buf, .. }
+                if matches!(
+                    path_buf.extension().and_then(|ext| ext.to_str()),
+                    Some(ext) if ext.eq_ignore_ascii_case("svg")
+                ) =>
+            {
+                Some(path_buf.clone())
+            }
+            _ => None,
+        })
+        .collect::<Vec<PathBuf>>();
+    if ctrl_pressed(&input) && input.just_pressed(KeyCode::I) {
+        paths.push(PathBuf::from(SVG_PATH));
+    }
+    for path in paths {
+        match import_svg(&path) {
+            Ok(records) => {
+                for record in records.iter() {
+                    let (entity, _) = spawn_shape(&mut commands, record);
+                    ordered.0.push(entity);
+                    history.send(HistoryEvent(EditAction::Created { entity }));
+                }
+                info!("imported {} shapes from {}", records.len(), path.display());
+            }
+            Err(e) => error!("failed to import {}: {}", path.display(), e),
+        }
+    }
+}
+
+pub fn import_svg(path: &Path) -> Result<Vec<ShapeRecord>, SvgImportError> {
+    let text = fs::read_to_string(path)?;
+    svg_to_shapes(&text)
+}
+
+pub fn svg_to_shapes(text: &str) -> Result<Vec<ShapeRecord>, SvgImportError> {
+    let document = roxmltree::Document::parse(text)?;
+    let mut records = vec![];
+    // SVG is y-down, the canvas is y-up.
+    let flip = Affine2::from_scale(Vec2::new(1.0, -1.0));
+    visit_node(
+        document.root_element(),
+        flip,
+        SvgStyle::default(),
+        &mut records,
+    );
+    Ok(records)
+}
+
+fn visit_node(
+    node: roxmltree::Node,
+    parent_transform: Affine2,
+    parent_style: SvgStyle,
+    records: &mut Vec<ShapeRecord>,
+) {
+    let transform = match node.attribute("transform") {
+        Some(t) => match svgtypes::Transform::from_str(t) {
+            Ok(t) => {
+                parent_transform
+                    * Affine2::from_cols_array(&[
+                        t.a as f32, t.b as f32, t.c as f32, t.d as f32, t.e as f32, t.f as f32,
+                    ])
+            }
+            Err(e) => {
+                warn!("ignoring invalid transform {:?}: {}", t, e);
+                parent_transform
+            }
+        },
+        None => parent_transform,
+    };
+    let style = node_style(&node, parent_style);
+    let name = node.attribute("id").map(String::from);
+
+    match node.tag_name().name() {
+        "svg" | "g" => {
+            for child in node.children().filter(|c| c.is_element()) {
+                visit_node(child, transform, style, records);
+            }
+        }
+        "path" => {
+            if let Some(d) = node.attribute("d") {
+                records.extend(contour_record(
+                    path_contours(d, transform),
+                    &style,
+                    transform,
+                    name,
+                ));
+            }
+        }
+        "polygon" | "polyline" => {
+            if let Some(points) = node.attribute("points") {
+                let points = PointsParser::from(points)
+                    .map(|(x, y)| transform.transform_point2(Vec2::new(x as f32, y as f32)))
+                    .collect::<Vec<Vec2>>();
+                let closed = node.tag_name().name() == "polygon";
+                records.extend(
+                    polyline_contour(&points, closed)
+                        .and_then(|c| contour_record(vec![c], &style, transform, name)),
+                );
+            }
+        }
+        "line" => {
+            let points = [
+                Vec2::new(length(&node, "x1"), length(&node, "y1")),
+                Vec2::new(length(&node, "x2"), length(&node, "y2")),
+            ]
+            .map(|p| transform.transform_point2(p));
+            records.extend(
+                polyline_contour(&points, false)
+                    .and_then(|c| contour_record(vec![c], &style, transform, name)),
+            );
+        }
+        "rect" => {
+            let position = Vec2::new(length(&node, "x"), length(&node, "y"));
+            let size = Vec2::new(length(&node, "width"), length(&node, "height"));
+            let radius = match (node.attribute("rx"), node.attribute("ry")) {
+                (None, None) => 0.0,
+                (Some(_), _) => length(&node, "rx"),
+                (None, Some(_)) => length(&node, "ry"),
+            };
+            records.extend(primitive_record(
+                PrimitiveShape {
+                    shape: if radius > 0.0 {
+                        PrimitiveType::RoundedRectangle
+                    } else {
+                        PrimitiveType::Rectangle
+                    },
+                    extents: size,
+                    corner_radii: [radius; 4],
+                },
+                position + size / 2.0,
+                &style,
+                transform,
+                name,
+            ));
+        }
+        "ellipse" | "circle" => {
+            let center = Vec2::new(length(&node, "cx"), length(&node, "cy"));
+            let radii = if node.tag_name().name() == "circle" {
+                Vec2::splat(length(&node, "r"))
+            } else {
+                Vec2::new(length(&node, "rx"), length(&node, "ry"))
+            };
+            records.extend(primitive_record(
+                PrimitiveShape {
+                    shape: PrimitiveType::Ellipse,
+                    extents: radii * 2.0,
+                    corner_radii: [0.0; 4],
+                },
+                center,
+                &style,
+                transform,
+                name,
+            ));
+        }
+        _ => {}
+    }
+}
+
+fn property<'a>(node: &roxmltree::Node<'a, '_>, name: &str) -> Option<&'a str> {
+    node.attribute("style")
+        .and_then(|style| {
+            style.split(';').find_map(|declaration| {
+                let (key, value) = declaration.split_once(':')?;
+                if key.trim() == name {
+                    Some(value.trim())
+                } else {
+                    None
+                }
+            })
+        })
+        .or_else(|| node.attribute(name))
+}
+
+fn length(node: &roxmltree::Node, name: &str) -> f32 {
+    node.attribute(name)
+        .and_then(|v| Length::from_str(v).ok())
+        .map_or(0.0, |l| l.number as f32)
+}
+
+fn number_property(node: &roxmltree::Node, name: &str) -> Option<f32> {
+    property(node, name)
+        .and_then(|v| Length::from_str(v).ok())
+        .map(|l| l.number as f32)
+}
+
+fn paint_property(node: &roxmltree::Node, name: &str, inherited: Option<Color>) -> Option<Color> {
+    match property(node, name).map(Paint::from_str) {
+        Some(Ok(Paint::None)) => None,
+        Some(Ok(Paint::Color(c))) => Some(Color::rgba_u8(c.red, c.green, c.blue, c.alpha)),
+        Some(Ok(Paint::CurrentColor)) => Some(Color::BLACK),
+        Some(Ok(Paint::FuncIRI(_, fallback))) => match fallback {
+            Some(svgtypes::PaintFallback::Color(c)) => {
+                Some(Color::rgba_u8(c.red, c.green, c.blue, c.alpha))
+            }
+            Some(svgtypes::PaintFallback::None) => None,
+            _ => Some(Color::BLACK),
+        },
+        _ => inherited,
+    }
+}
+
+fn node_style(node: &roxmltree::Node, parent: SvgStyle) -> SvgStyle {
+    SvgStyle {
+        fill: paint_property(node, "fill", parent.fill),
+        fill_rule: match property(node, "fill-rule") {
+            Some("nonzero") => FillRuleRecord::NonZero,
+            Some("evenodd") => FillRuleRecord::EvenOdd,
+            _ => parent.fill_rule,
+        },
+        stroke: paint_property(node, "stroke", parent.stroke),
+        stroke_width: number_property(node, "stroke-width").unwrap_or(parent.stroke_width),
+        cap: match property(node, "stroke-linecap") {
+            Some("butt") => CapRecord::Butt,
+            Some("square") => CapRecord::Square,
+            Some("round") => CapRecord::Round,
+            _ => parent.cap,
+        },
+        join: match property(node, "stroke-linejoin") {
+            Some("miter") | Some("miter-clip") | Some("arcs") => JoinRecord::Miter,
+            Some("round") => JoinRecord::Round,
+            Some("bevel") => JoinRecord::Bevel,
+            _ => parent.join,
+        },
+        miter_limit: number_property(node, "stroke-miterlimit")
+            .map_or(parent.miter_limit, |limit| {
+                limit.max(StrokeOptions::MINIMUM_MITER_LIMIT)
+            }),
+        fill_opacity: number_property(node, "fill-opacity").unwrap_or(parent.fill_opacity),
+        stroke_opacity: number_property(node, "stroke-opacity").unwrap_or(parent.stroke_opacity),
+        opacity: parent.opacity * number_property(node, "opacity").unwrap_or(1.0),
+    }
+}
+
+fn style_record(style: &SvgStyle, stroke_scale: f32) -> Option<StyleRecord> {
+    let fill = style.fill.map(|mut color| {
+        color.set_a(color.a() * style.fill_opacity * style.opacity);
+        FillRecord {
+            color,
+            rule: style.fill_rule,
+        }
+    });
+    let stroke = style.stroke.map(|mut color| {
+        color.set_a(color.a() * style.stroke_opacity * style.opacity);
+        StrokeRecord {
+            color,
+            width: style.stroke_width * stroke_scale,
+            cap: style.cap,
+            join: style.join,
+            miter_limit: style.miter_limit,
+        }
+    });
+    match (fill, stroke) {
+        (Some(fill), Some(outline)) => Some(StyleRecord::Outlined { fill, outline }),
+        (Some(fill), None) => Some(StyleRecord::Fill(fill)),
+        (None, Some(stroke)) => Some(StyleRecord::Stroke(stroke)),
+        (None, None) => None,
+    }
+}
+
+fn path_contours(d: &str, transform: Affine2) -> Vec<Contour> {
+    let mut contours = vec![];
+    let mut current: Option<Contour> = None;
+    let point = |x: f64, y: f64| transform.transform_point2(Vec2::new(x as f32, y as f32));
+    for segment in SimplifyingPathParser::from(d) {
+        let segment = match segment {
+            Ok(s) => s,
+            Err(e) => {
+                warn!("stopped parsing path data: {}", e);
+                break;
+            }
+        };
+        if let SimplePathSegment::MoveTo { x, y } = segment {
+            contours.extend(current.take());
+            let start = point(x, y);
+            current = Some(Contour {
+                start,
+                segments: vec![],
+                points: vec![start],
+                closed: false,
+            });
+            continue;
+        }
+        let contour = match current.as_mut() {
+            Some(c) => c,
+            None => continue,
+        };
+        match segment {
+            SimplePathSegment::LineTo { x, y } => {
+                let to = point(x, y);
+                contour
+                    .segments
+                    .push(ShapeSegment::Line(point_from_positions(to, contour.start)));
+                contour.points.push(to);
+            }
+            SimplePathSegment::Quadratic { x1, y1, x, y } => {
+                let (ctrl, to) = (point(x1, y1), point(x, y));
+                contour.segments.push(ShapeSegment::QuadraticBezier {
+                    ctrl: point_from_positions(ctrl, contour.start),
+                    to: point_from_positions(to, contour.start),
+                });
+                contour.points.extend([ctrl, to]);
+            }
+            SimplePathSegment::CurveTo {
+                x1,
+                y1,
+                x2,
+                y2,
+                x,
+                y,
+            } => {
+                let (ctrl, ctrl2, to) = (point(x1, y1), point(x2, y2), point(x, y));
+                contour.segments.push(ShapeSegment::CubicBezier {
+                    ctrl: point_from_positions(ctrl, contour.start),
+                    ctrl2: point_from_positions(ctrl2, contour.start),
+                    to: point_from_positions(to, contour.start),
+                });
+                contour.points.extend([ctrl, ctrl2, to]);
+            }
+            SimplePathSegment::ClosePath => {
+                contour.closed = true;
+                contours.extend(current.take());
+            }
+            SimplePathSegment::MoveTo { .. } => unreachable!(),
+        }
+    }
+    contours.extend(current);
+    contours
+}
+
+fn polyline_contour(points: &[Vec2], closed: bool) -> Option<Contour> {
+    let (start, rest) = points.split_first()?;
+    Some(Contour {
+        start: *start,
+        segments: rest
+            .iter()
+            .map(|p| ShapeSegment::Line(point_from_positions(*p, *start)))
+            .collect(),
+        points: points.to_vec(),
+        closed,
+    })
+}
+
+/// The contours of a path make up a single shape, the ones after the first become subpaths.
+fn contour_record(
+    contours: Vec<Contour>,
+    style: &SvgStyle,
+    transform: Affine2,
+    name: Option<String>,
+) -> Option<ShapeRecord> {
+    let mut contours = contours.into_iter().filter(|c| !c.segments.is_empty());
+    let contour = contours.next()?;
+    let rest = contours.collect::<Vec<_>>();
+    let points = contour
+        .points
+        .iter()
+        .chain(rest.iter().flat_map(|c| c.points.iter()));
+    let (min, max) = points.fold(
+        (Vec2::splat(f32::MAX), Vec2::splat(f32::MIN)),
+        |(min, max), p| (min.min(*p), max.max(*p)),
+    );
+    let center = (min + max) / 2.0;
+    let stroke_scale = transform.matrix2.determinant().abs().sqrt();
+    Some(ShapeRecord {
+        name,
+        translation: center.extend(0.0),
+        rotation: Quat::IDENTITY,
+        scale: Vec3::ONE,
+        style: Some(style_record(style, stroke_scale)?),
+        gradient: None,
+        dashes: None,
+        markers: None,
+        connector: None,
+        geometry: GeometryRecord::Custom(CustomShapeRaw {
+            segments: contour.segments,
+            closed: contour.closed,
+            origin: contour.start - center,
+            subpaths: rest
+                .into_iter()
+                .map(|c| {
+                    let offset = point_from_positions(c.start, contour.start).to_vector();
+                    Subpath {
+                        start: offset.to_point(),
+                        segments: c.segments.iter().map(|s| s.map(|p| p + offset)).collect(),
+                        closed: c.closed,
+                    }
+                })
+                .collect(),
+        }),
+        locked: false,
+        hidden: false,
+    })
+}
+
+fn primitive_record(
+    primitive: PrimitiveShape,
+    center: Vec2,
+    style: &SvgStyle,
+    transform: Affine2,
+    name: Option<String>,
+) -> Option<ShapeRecord> {
+    if primitive.extents.x <= 0.0 || primitive.extents.y <= 0.0 {
+        return None;
+    }
+    let x_axis = transform.matrix2.x_axis;
+    let scale_x = x_axis.length();
+    let scale_y = transform.matrix2.determinant().abs() / scale_x;
+    Some(ShapeRecord {
+        name,
+        translation: transform.transform_point2(center).extend(0.0),
+        rotation: Quat::from_rotation_z(x_axis.y.atan2(x_axis.x)),
+        scale: Vec3::new(scale_x, scale_y, 1.0),
+        style: Some(style_record(style, 1.0)?),
+        gradient: None,
+        dashes: None,
+        markers: None,
+        connector: None,
+        geometry: GeometryRecord::Primitive(primitive),
+        locked: false,
+        hidden: false,
+    })
+}
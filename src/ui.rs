@@ -1,10 +1,25 @@
-use crate::{MouseMovement, PrimitiveType, ShapeBase, Tool, ToolChanged, ToolType};
+use crate::align::{AlignEvent, Alignment};
+use crate::connector::{AnchorPoint, Connector, Routing};
+use crate::document::GeometryRecord;
+use crate::gradient::{Gradient, GradientKind, GradientStop, MAX_STOPS};
+use crate::grid::Grid;
+use crate::helpers::{shape_bounds, BoundsQuery};
+use crate::history::{EditAction, HistoryEvent};
+use crate::layers::{restack, set_layer_flags, Hidden, LayerFlagsQuery, Locked};
+use crate::naming::display_name;
+use crate::path_ops::{PathOpEvent, PathOperation};
+use crate::snapping::ObjectSnap;
+use crate::stroke::{Dashes, Marker, Markers};
+use crate::{
+    MouseMovement, OrderedShapes, PrimitiveShape, PrimitiveType, ShapeBase, Tool, ToolChanged,
+    ToolType,
+};
 use bevy::prelude::*;
 use bevy_egui::egui::Color32;
 use bevy_egui::{egui, EguiContext};
 use bevy_mod_picking::Selection;
 use bevy_prototype_lyon::draw::{DrawMode, FillMode, StrokeMode};
-use std::cmp::Ordering;
+use bevy_prototype_lyon::prelude::{FillRule, LineCap, LineJoin, Path, ShapePath, StrokeOptions};
 
 pub struct UIPlugin;
 impl Plugin for UIPlugin {
@@ -12,6 +27,12 @@ impl Plugin for UIPlugin {
         app.add_system(ui_example.label("egui"))
             .add_system(objects_list.label("egui"))
             .add_system(edit_style.label("egui"))
+            .add_system(edit_properties.label("egui"))
+            .add_system(edit_connector.label("egui"))
+            .add_system(grid_settings.label("egui"))
+            .add_system(align_panel.label("egui"))
+            .add_system(path_panel.label("egui"))
+            .add_system(edit_transform.label("egui"))
             .add_event::<ToolChanged>();
     }
 }
@@ -27,12 +48,31 @@ fn ui_example(
         let mut prim_type = PrimitiveType::Rectangle;
         if let ToolType::Primitive(mut sh) = current.tool {
             ui.horizontal(|ui| {
-                ui.selectable_value(&mut sh, PrimitiveType::Rectangle, "Rectangle");
-                ui.selectable_value(&mut sh, PrimitiveType::Ellipse, "Ellipse");
+                for (shape, label) in PrimitiveType::ALL {
+                    let selected = std::mem::discriminant(&sh) == std::mem::discriminant(&shape);
+                    if ui.selectable_label(selected, label).clicked() && !selected {
+                        sh = shape;
+                    }
+                }
             });
+            if sh == PrimitiveType::RoundedRectangle {
+                ui.horizontal(|ui| {
+                    ui.label("Corner radius");
+                    ui.add(
+                        egui::DragValue::new(&mut current.corner_radius).clamp_range(0.0..=1000.0),
+                    );
+                });
+            }
+            polygon_widgets(ui, &mut sh);
             prim_type = sh;
             current.tool = ToolType::Primitive(sh);
         }
+        if current.tool == ToolType::Connector {
+            routing_widgets(ui, &mut current.routing);
+        }
+        if let ToolType::Line | ToolType::Connector = current.tool {
+            marker_widgets(ui, &mut current.markers);
+        }
         ui.label("Choose drawing mode");
         ui.horizontal(|ui| {
             ui.selectable_value(&mut current.tool, ToolType::None, "None");
@@ -42,6 +82,9 @@ fn ui_example(
                 "Primitive",
             );
             ui.selectable_value(&mut current.tool, ToolType::CustomShape, "Custom Shape");
+            ui.selectable_value(&mut current.tool, ToolType::Line, "Line");
+            ui.selectable_value(&mut current.tool, ToolType::Connector, "Connector");
+            ui.selectable_value(&mut current.tool, ToolType::EditPoints, "Edit Points");
         });
         ui.end_row();
         ui.label("Choose shape color");
@@ -54,51 +97,264 @@ fn ui_example(
     }
 }
 
+type ObjectEntryQuery<'a> = (
+    &'a Selection,
+    &'a ShapeBase,
+    Option<&'a Hidden>,
+    Option<&'a Locked>,
+    Option<&'a Children>,
+);
+
+#[derive(Default)]
+struct ObjectsListState {
+    filter: String,
+    renaming: Option<(Entity, String)>,
+    dragging: Option<Entity>,
+}
+
+/// What was clicked in the Objects window during this frame.
+#[derive(Default)]
+struct ObjectsListActions {
+    selected: Option<Entity>,
+    renamed: Option<(Entity, String)>,
+    toggled_visibility: Option<Entity>,
+    toggled_lock: Option<Entity>,
+    /// Top-level rows, from the top of the stack down, for dropping a dragged row between them.
+    rows: Vec<(Entity, egui::Rect)>,
+}
+
+/// Whether the shape or one of the shapes grouped under it matches the filter.
+fn matches_filter(
+    e: Entity,
+    query: &Query<ObjectEntryQuery, With<ShapeBase>>,
+    filter: &str,
+) -> bool {
+    match query.get(e) {
+        Ok((_, base, ..)) if display_name(base, e).to_lowercase().contains(filter) => true,
+        Ok((.., Some(children))) => children.iter().any(|c| matches_filter(*c, query, filter)),
+        _ => false,
+    }
+}
+
+fn object_entry(
+    ui: &mut egui::Ui,
+    e: Entity,
+    root: Entity,
+    query: &Query<ObjectEntryQuery, With<ShapeBase>>,
+    state: &mut ObjectsListState,
+    actions: &mut ObjectsListActions,
+) {
+    let filter = state.filter.to_lowercase();
+    if !matches_filter(e, query, &filter) {
+        return;
+    }
+    let (selection, base, hidden, locked, children) = match query.get(e) {
+        Ok(entry) => entry,
+        Err(_) => return,
+    };
+    let label = |ui: &mut egui::Ui| {
+        if ui.selectable_label(hidden.is_none(), "👁").clicked() {
+            actions.toggled_visibility = Some(e);
+        }
+        if ui.selectable_label(locked.is_some(), "🔒").clicked() {
+            actions.toggled_lock = Some(e);
+        }
+        if let Some((_, text)) = state.renaming.as_mut().filter(|(r, _)| *r == e) {
+            let response = ui.text_edit_singleline(text);
+            response.request_focus();
+            if ui.input().key_pressed(egui::Key::Escape) {
+                state.renaming = None;
+            } else if response.lost_focus() {
+                actions.renamed = state.renaming.take();
+            }
+            return;
+        }
+        let response = ui
+            .selectable_label(selection.selected(), display_name(base, e))
+            .interact(egui::Sense::drag());
+        // Grouped shapes can't be selected on their own, clicking them selects the group.
+        if response.clicked() {
+            actions.selected = Some(root);
+        }
+        if response.double_clicked() {
+            state.renaming = Some((e, base.name.clone().unwrap_or_default()));
+        }
+        // Only top-level rows can be dragged, grouped shapes move with their group.
+        if e == root {
+            if response.drag_started() {
+                state.dragging = Some(e);
+            }
+            actions.rows.push((e, ui.min_rect()));
+        }
+    };
+    match children {
+        Some(children) => {
+            egui::collapsing_header::CollapsingState::load_with_default_open(
+                ui.ctx(),
+                ui.make_persistent_id(e),
+                false,
+            )
+            .show_header(ui, label)
+            .body(|ui| {
+                for child in children.iter().rev() {
+                    object_entry(ui, *child, root, query, state, actions);
+                }
+            });
+        }
+        None => {
+            ui.horizontal(label);
+        }
+    }
+}
+
+/// Index in `OrderedShapes` a row dropped at height `y` of the list ends up at, along with
+/// the height of the gap it is dropped into.
+fn drop_target(
+    rows: &[(Entity, egui::Rect)],
+    ordered: &[Entity],
+    dragged: Entity,
+    y: f32,
+) -> (usize, f32) {
+    let remaining = ordered.iter().filter(|e| **e != dragged);
+    match rows
+        .iter()
+        .find(|(e, rect)| *e != dragged && y < rect.center().y)
+    {
+        // Rows are listed from the top of the stack down, so dropping above one means on top of it.
+        Some((above, rect)) => (
+            remaining.take_while(|e| *e != above).count() + 1,
+            rect.top(),
+        ),
+        None => (0, rows.last().map_or(y, |(_, rect)| rect.bottom())),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn objects_list(
+    mut commands: Commands,
     mut egui_context: ResMut<EguiContext>,
-    mut query: Query<(&ShapeBase, &mut Selection, Entity, &Transform)>,
+    mut state: Local<ObjectsListState>,
+    mut query: ParamSet<(
+        Query<ObjectEntryQuery, With<ShapeBase>>,
+        Query<&mut Selection, With<ShapeBase>>,
+        Query<&mut ShapeBase>,
+    )>,
+    flags: LayerFlagsQuery,
+    mut ordered: ResMut<OrderedShapes>,
     mut mouse: ResMut<MouseMovement>,
+    mut history: EventWriter<HistoryEvent>,
 ) {
-    let mut selected = None;
+    let mut actions = ObjectsListActions::default();
+    let mut stack = None;
+    let mut dropped = None;
     egui::Window::new("Objects").show(egui_context.ctx_mut(), |ui| {
+        ui.horizontal(|ui| {
+            ui.label("Filter");
+            ui.text_edit_singleline(&mut state.filter);
+        });
+        ui.horizontal(|ui| {
+            if ui.button("Bring to Front").clicked() {
+                stack = Some(true);
+            }
+            if ui.button("Send to Back").clicked() {
+                stack = Some(false);
+            }
+        });
         ui.vertical(|ui| {
-            let mut ents: Vec<(&ShapeBase, &Selection, Entity, &Transform)> =
-                query.iter().collect();
-            ents.sort_by(|a, b| {
-                b.3.translation
-                    .z
-                    .partial_cmp(&a.3.translation.z)
-                    .unwrap_or(Ordering::Equal)
-            });
-            ents.iter().for_each(|(_name, selection, e, t)| {
-                if ui
-                    .selectable_label(
-                        selection.selected(),
-                        format!("{:?}: {}", e, t.translation.z),
-                    )
-                    .clicked()
-                {
-                    selected = Some(*e);
-                }
-            });
+            for e in ordered.0.iter().rev() {
+                object_entry(ui, *e, *e, &query.p0(), &mut state, &mut actions);
+            }
         });
+        if let Some(dragged) = state.dragging {
+            let pointer = ui.input().pointer.interact_pos();
+            if let Some(y) = pointer.map(|p| p.y) {
+                let (index, line) = drop_target(&actions.rows, &ordered.0, dragged, y);
+                let width = ui.min_rect().left()..=ui.min_rect().right();
+                ui.painter()
+                    .hline(width, line, (2.0, ui.visuals().selection.bg_fill));
+                if ui.input().pointer.any_released() {
+                    dropped = Some((dragged, index));
+                }
+            }
+            if ui.input().pointer.any_released() {
+                state.dragging = None;
+            }
+        }
     });
-    if let Some(e) = selected {
-        for (_, mut sel, _, _) in query.iter_mut() {
+    if let Some(e) = actions.selected {
+        for mut sel in query.p1().iter_mut() {
             sel.set_selected(false);
         }
-        if let Ok(mut comp) = query.get_component_mut::<Selection>(e) {
+        if let Ok(mut comp) = query.p1().get_mut(e) {
             comp.set_selected(true);
         }
     }
-
+    if let Some((e, name)) = actions.renamed {
+        let name = Some(name.trim().to_string()).filter(|n| !n.is_empty());
+        if let Ok(mut base) = query.p2().get_mut(e) {
+            if base.name != name {
+                history.send(HistoryEvent(EditAction::Renamed {
+                    entity: e,
+                    before: base.name.clone(),
+                    after: name.clone(),
+                }));
+                base.name = name;
+            }
+        }
+    }
+    let toggled = actions.toggled_visibility.or(actions.toggled_lock);
+    if let Some((e, Ok((_, locked, hidden)))) = toggled.map(|e| (e, flags.get(e))) {
+        let before = (locked.is_some(), hidden.is_some());
+        let locked = before.0 != (actions.toggled_lock == Some(e));
+        let hidden = before.1 != (actions.toggled_visibility == Some(e));
+        set_layer_flags(&mut commands, e, locked, hidden, &flags);
+        history.send(HistoryEvent(EditAction::LayerFlagsChanged {
+            entity: e,
+            before,
+            after: (locked, hidden),
+        }));
+        // Shapes that can't be picked any more can't stay selected either.
+        if locked || hidden {
+            if let Ok(mut selection) = query.p1().get_mut(e) {
+                selection.set_selected(false);
+            }
+        }
+    }
+    let before = ordered.0.clone();
+    if let Some(front) = stack {
+        let selected = ordered
+            .0
+            .iter()
+            .copied()
+            .filter(|e| matches!(query.p1().get(*e), Ok(s) if s.selected()))
+            .collect::<Vec<_>>();
+        restack(&mut ordered, &selected, front);
+    }
+    if let Some((e, index)) = dropped {
+        ordered.0.retain(|o| *o != e);
+        let index = index.min(ordered.0.len());
+        ordered.0.insert(index, e);
+    }
+    if ordered.0 != before {
+        history.send(HistoryEvent(EditAction::Reordered {
+            before,
+            after: ordered.0.clone(),
+        }));
+    }
     mouse.over_ui = egui_context.ctx_mut().wants_pointer_input();
 }
+
+#[allow(clippy::too_many_arguments)]
 fn edit_style(
+    mut commands: Commands,
     mut egui_context: ResMut<EguiContext>,
     mut query: Query<&mut DrawMode>,
+    gradients: Query<Option<&Gradient>, With<ShapeBase>>,
+    dashes: Query<Option<&Dashes>, With<ShapeBase>>,
+    markers: Query<Option<&Markers>, With<ShapeBase>>,
     mut mouse: ResMut<MouseMovement>,
     query2: Query<(&Selection, Entity)>,
+    mut history: EventWriter<HistoryEvent>,
 ) {
     let x: Vec<Entity> = query2
         .iter()
@@ -109,135 +365,815 @@ fn edit_style(
             None
         })
         .collect();
-    if x.len() == 1 {
-        if let Ok(mut draw_mode) = query.get_component_mut::<DrawMode>(x[0]) {
-            egui::Window::new("Edit Style").show(egui_context.ctx_mut(), |ui| match *draw_mode {
-                DrawMode::Fill(fill_mode) => {
-                    let mut edited = false;
-                    ui.horizontal(|ui| {
-                        let _ = ui.selectable_label(true, "Fill");
-                        if ui.selectable_label(false, "Stroke").clicked() {
-                            *draw_mode = DrawMode::Stroke(StrokeMode::new(Color::BLACK, 5.0));
-                            edited = true;
-                            return;
-                        }
-                        if ui.selectable_label(false, "Outlined").clicked() {
-                            *draw_mode = DrawMode::Outlined {
-                                fill_mode,
-                                outline_mode: StrokeMode::new(Color::BLACK, 5.0),
-                            };
-                            edited = true;
-                            return;
+    if !x.is_empty() {
+        if let Ok(current) = query.get_component::<DrawMode>(x[0]) {
+            let current = *current;
+            let mut style = current;
+            let draw_mode = &mut style;
+            let current_gradient = gradients.get(x[0]).ok().flatten().cloned();
+            let mut gradient = current_gradient.clone();
+            let current_dashes = dashes.get(x[0]).ok().flatten().cloned();
+            let mut dash_pattern = current_dashes.clone();
+            let current_markers = markers.get(x[0]).ok().flatten().copied();
+            let mut end_markers = current_markers.unwrap_or_default();
+            egui::Window::new("Edit Style").show(egui_context.ctx_mut(), |ui| {
+                style_widgets(ui, draw_mode);
+                if let DrawMode::Stroke(stroke_mode)
+                | DrawMode::Outlined {
+                    outline_mode: stroke_mode,
+                    ..
+                } = draw_mode
+                {
+                    ui.separator();
+                    stroke_options_widgets(ui, &mut stroke_mode.options);
+                    dash_widgets(ui, &mut dash_pattern);
+                    marker_widgets(ui, &mut end_markers);
+                }
+                let fill = match draw_mode {
+                    DrawMode::Fill(fill_mode) | DrawMode::Outlined { fill_mode, .. } => fill_mode,
+                    DrawMode::Stroke(_) => return,
+                };
+                ui.separator();
+                ui.horizontal(|ui| {
+                    ui.label("Fill rule");
+                    let rule = &mut fill.options.fill_rule;
+                    ui.selectable_value(rule, FillRule::EvenOdd, "Even-odd");
+                    ui.selectable_value(rule, FillRule::NonZero, "Non-zero");
+                });
+                let fill = fill.color;
+                gradient_widgets(ui, &mut gradient, fill);
+            });
+            // The first selected shape drives the window, the edited fields apply to the whole
+            // selection.
+            if style != current {
+                for e in x.iter() {
+                    if let Ok(mut draw_mode) = query.get_mut(*e) {
+                        let after = apply_style_change(&current, &style, &draw_mode);
+                        if *draw_mode != after {
+                            history.send(HistoryEvent(EditAction::Restyled {
+                                entity: *e,
+                                before: *draw_mode,
+                                after,
+                            }));
+                            *draw_mode = after;
                         }
-                    });
-                    if edited {
-                        return;
                     }
-                    let col = fill_mode.color.as_rgba_f32();
-                    let mut color = Color32::from_rgba_premultiplied(
-                        (col[0] * 256.0) as u8,
-                        (col[1] * 256.0) as u8,
-                        (col[2] * 256.0) as u8,
-                        (col[3] * 256.0) as u8,
-                    );
-                    ui.color_edit_button_srgba(&mut color);
-                    *draw_mode = DrawMode::Fill(FillMode::color(Color::rgba_u8(
-                        color.r(),
-                        color.g(),
-                        color.b(),
-                        color.a(),
-                    )));
-                }
-                DrawMode::Stroke(stroke_mode) => {
-                    let mut edited = false;
-                    ui.horizontal(|ui| {
-                        if ui.selectable_label(false, "Fill").clicked() {
-                            *draw_mode = DrawMode::Fill(FillMode::color(Color::BLACK));
-                            edited = true;
-                            return;
-                        }
-                        let _ = ui.selectable_label(true, "Stroke");
-                        if ui.selectable_label(false, "Outlined").clicked() {
-                            *draw_mode = DrawMode::Outlined {
-                                fill_mode: FillMode::color(Color::BLACK),
-                                outline_mode: stroke_mode,
-                            };
-                            edited = true;
-                            return;
-                        }
-                    });
-                    if edited {
-                        return;
+                }
+            }
+            if dash_pattern != current_dashes && !matches!(style, DrawMode::Fill(_)) {
+                for e in x.iter() {
+                    let before = match dashes.get(*e) {
+                        Ok(before) => before.cloned(),
+                        Err(_) => continue,
+                    };
+                    if before == dash_pattern {
+                        continue;
                     }
-                    let mut num = stroke_mode.options.line_width;
-                    let col = stroke_mode.color.as_rgba_f32();
-                    let mut color = Color32::from_rgba_premultiplied(
-                        (col[0] * 256.0) as u8,
-                        (col[1] * 256.0) as u8,
-                        (col[2] * 256.0) as u8,
-                        (col[3] * 256.0) as u8,
-                    );
-                    ui.add(egui::DragValue::new(&mut num));
-                    ui.color_edit_button_srgba(&mut color);
-                    *draw_mode = DrawMode::Stroke(StrokeMode::new(
-                        Color::rgba_u8(color.r(), color.g(), color.b(), color.a()),
-                        num,
-                    ));
-                }
-                DrawMode::Outlined {
-                    outline_mode,
-                    fill_mode,
-                } => {
-                    let mut edited = false;
-                    ui.horizontal(|ui| {
-                        if ui.selectable_label(false, "Fill").clicked() {
-                            *draw_mode = DrawMode::Fill(fill_mode);
-                            edited = true;
-                            return;
-                        }
-                        if ui.selectable_label(false, "Stroke").clicked() {
-                            *draw_mode = DrawMode::Stroke(outline_mode);
-                            edited = true;
-                            return;
-                        }
-                        let _ = ui.selectable_label(true, "Outlined");
+                    match &dash_pattern {
+                        Some(after) => commands.entity(*e).insert(after.clone()),
+                        None => commands.entity(*e).remove::<Dashes>(),
+                    };
+                    history.send(HistoryEvent(EditAction::DashesChanged {
+                        entity: *e,
+                        before,
+                        after: dash_pattern.clone(),
+                    }));
+                }
+            }
+            // Shapes without any markers don't keep an empty component around.
+            let end_markers = Some(end_markers).filter(|m| *m != Markers::default());
+            if end_markers != current_markers && !matches!(style, DrawMode::Fill(_)) {
+                for e in x.iter() {
+                    let before = match markers.get(*e) {
+                        Ok(before) => before.copied(),
+                        Err(_) => continue,
+                    };
+                    if before == end_markers {
+                        continue;
+                    }
+                    match end_markers {
+                        Some(after) => commands.entity(*e).insert(after),
+                        None => commands.entity(*e).remove::<Markers>(),
+                    };
+                    history.send(HistoryEvent(EditAction::MarkersChanged {
+                        entity: *e,
+                        before,
+                        after: end_markers,
+                    }));
+                }
+            }
+            if gradient != current_gradient {
+                for e in x.iter() {
+                    let before = match gradients.get(*e) {
+                        Ok(before) => before.cloned(),
+                        Err(_) => continue,
+                    };
+                    // Each shape keeps its own handles, unless the kind of gradient changed.
+                    let after = gradient.as_ref().map(|edited| match &before {
+                        Some(before) if before.kind == edited.kind => Gradient {
+                            stops: edited.stops.clone(),
+                            ..before.clone()
+                        },
+                        _ => Gradient {
+                            stops: edited.stops.clone(),
+                            ..Gradient::new(edited.kind, fill_color(&style))
+                        },
                     });
-                    if edited {
-                        return;
+                    if before == after || matches!(style, DrawMode::Stroke(_)) {
+                        continue;
                     }
-                    let col = fill_mode.color.as_rgba_f32();
-                    let mut color = Color32::from_rgba_premultiplied(
-                        (col[0] * 256.0) as u8,
-                        (col[1] * 256.0) as u8,
-                        (col[2] * 256.0) as u8,
-                        (col[3] * 256.0) as u8,
-                    );
-                    let stroke_col = outline_mode.color.as_rgba_f32();
-                    let mut color2 = Color32::from_rgba_premultiplied(
-                        (stroke_col[0] * 256.0) as u8,
-                        (stroke_col[1] * 256.0) as u8,
-                        (stroke_col[2] * 256.0) as u8,
-                        (stroke_col[3] * 256.0) as u8,
-                    );
-                    let mut num = outline_mode.options.line_width;
-                    ui.color_edit_button_srgba(&mut color);
-                    ui.add(egui::DragValue::new(&mut num));
-                    ui.color_edit_button_srgba(&mut color2);
+                    match &after {
+                        Some(after) => commands.entity(*e).insert(after.clone()),
+                        None => commands.entity(*e).remove::<Gradient>(),
+                    };
+                    history.send(HistoryEvent(EditAction::GradientChanged {
+                        entity: *e,
+                        before,
+                        after,
+                    }));
+                }
+            }
+        }
+        mouse.over_ui = egui_context.ctx_mut().wants_pointer_input();
+    }
+}
+
+/// Fill, stroke and outline settings of `draw_mode`.
+fn style_parts(draw_mode: &DrawMode) -> (Option<FillMode>, Option<StrokeMode>) {
+    match *draw_mode {
+        DrawMode::Fill(fill_mode) => (Some(fill_mode), None),
+        DrawMode::Stroke(stroke_mode) => (None, Some(stroke_mode)),
+        DrawMode::Outlined {
+            fill_mode,
+            outline_mode,
+        } => (Some(fill_mode), Some(outline_mode)),
+    }
+}
+
+fn apply_field<T: PartialEq + Copy>(before: T, after: T, target: &mut T) {
+    if before != after {
+        *target = after;
+    }
+}
+
+/// The style of `target` with only the fields changed from `before` to `after` applied.
+fn apply_style_change(before: &DrawMode, after: &DrawMode, target: &DrawMode) -> DrawMode {
+    let (before_fill, before_stroke) = style_parts(before);
+    let (after_fill, after_stroke) = style_parts(after);
+    let (target_fill, target_stroke) = style_parts(target);
+    // Shapes switching to a mode they don't have a part for take it from the edited shape.
+    let mut fill = target_fill.or(after_fill);
+    let mut stroke = target_stroke.or(after_stroke);
+    if let (Some(before), Some(after), Some(fill)) = (before_fill, after_fill, &mut fill) {
+        apply_field(before.color, after.color, &mut fill.color);
+        apply_field(
+            before.options.fill_rule,
+            after.options.fill_rule,
+            &mut fill.options.fill_rule,
+        );
+    }
+    if let (Some(before), Some(after), Some(stroke)) = (before_stroke, after_stroke, &mut stroke) {
+        let (b, a, t) = (before.options, after.options, &mut stroke.options);
+        apply_field(before.color, after.color, &mut stroke.color);
+        apply_field(b.line_width, a.line_width, &mut t.line_width);
+        apply_field(b.start_cap, a.start_cap, &mut t.start_cap);
+        apply_field(b.end_cap, a.end_cap, &mut t.end_cap);
+        apply_field(b.line_join, a.line_join, &mut t.line_join);
+        apply_field(b.miter_limit, a.miter_limit, &mut t.miter_limit);
+    }
+    let mode = if std::mem::discriminant(before) != std::mem::discriminant(after) {
+        after
+    } else {
+        target
+    };
+    match (mode, fill, stroke) {
+        (DrawMode::Fill(_), Some(fill_mode), _) => DrawMode::Fill(fill_mode),
+        (DrawMode::Stroke(_), _, Some(stroke_mode)) => DrawMode::Stroke(stroke_mode),
+        (DrawMode::Outlined { .. }, Some(fill_mode), Some(outline_mode)) => DrawMode::Outlined {
+            fill_mode,
+            outline_mode,
+        },
+        _ => *target,
+    }
+}
+
+fn style_widgets(ui: &mut egui::Ui, draw_mode: &mut DrawMode) {
+    match *draw_mode {
+        DrawMode::Fill(fill_mode) => {
+            let mut edited = false;
+            ui.horizontal(|ui| {
+                let _ = ui.selectable_label(true, "Fill");
+                if ui.selectable_label(false, "Stroke").clicked() {
+                    *draw_mode = DrawMode::Stroke(StrokeMode::new(Color::BLACK, 5.0));
+                    edited = true;
+                    return;
+                }
+                if ui.selectable_label(false, "Outlined").clicked() {
                     *draw_mode = DrawMode::Outlined {
-                        fill_mode: FillMode::color(Color::rgba_u8(
-                            color.r(),
-                            color.g(),
-                            color.b(),
-                            color.a(),
-                        )),
-                        outline_mode: StrokeMode::new(
-                            Color::rgba_u8(color2.r(), color2.g(), color2.b(), color2.a()),
-                            num,
-                        ),
+                        fill_mode,
+                        outline_mode: StrokeMode::new(Color::BLACK, 5.0),
                     };
+                    edited = true;
+                    return;
+                }
+            });
+            if edited {
+                return;
+            }
+            let col = fill_mode.color.as_rgba_f32();
+            let mut color = Color32::from_rgba_premultiplied(
+                (col[0] * 256.0) as u8,
+                (col[1] * 256.0) as u8,
+                (col[2] * 256.0) as u8,
+                (col[3] * 256.0) as u8,
+            );
+            ui.color_edit_button_srgba(&mut color);
+            *draw_mode = DrawMode::Fill(FillMode {
+                options: fill_mode.options,
+                color: Color::rgba_u8(color.r(), color.g(), color.b(), color.a()),
+            });
+        }
+        DrawMode::Stroke(stroke_mode) => {
+            let mut edited = false;
+            ui.horizontal(|ui| {
+                if ui.selectable_label(false, "Fill").clicked() {
+                    *draw_mode = DrawMode::Fill(FillMode::color(Color::BLACK));
+                    edited = true;
+                    return;
+                }
+                let _ = ui.selectable_label(true, "Stroke");
+                if ui.selectable_label(false, "Outlined").clicked() {
+                    *draw_mode = DrawMode::Outlined {
+                        fill_mode: FillMode::color(Color::BLACK),
+                        outline_mode: stroke_mode,
+                    };
+                    edited = true;
+                    return;
+                }
+            });
+            if edited {
+                return;
+            }
+            let mut num = stroke_mode.options.line_width;
+            let col = stroke_mode.color.as_rgba_f32();
+            let mut color = Color32::from_rgba_premultiplied(
+                (col[0] * 256.0) as u8,
+                (col[1] * 256.0) as u8,
+                (col[2] * 256.0) as u8,
+                (col[3] * 256.0) as u8,
+            );
+            ui.add(egui::DragValue::new(&mut num));
+            ui.color_edit_button_srgba(&mut color);
+            *draw_mode = DrawMode::Stroke(StrokeMode {
+                options: stroke_mode.options.with_line_width(num),
+                color: Color::rgba_u8(color.r(), color.g(), color.b(), color.a()),
+            });
+        }
+        DrawMode::Outlined {
+            outline_mode,
+            fill_mode,
+        } => {
+            let mut edited = false;
+            ui.horizontal(|ui| {
+                if ui.selectable_label(false, "Fill").clicked() {
+                    *draw_mode = DrawMode::Fill(fill_mode);
+                    edited = true;
+                    return;
+                }
+                if ui.selectable_label(false, "Stroke").clicked() {
+                    *draw_mode = DrawMode::Stroke(outline_mode);
+                    edited = true;
+                    return;
                 }
+                let _ = ui.selectable_label(true, "Outlined");
             });
+            if edited {
+                return;
+            }
+            let col = fill_mode.color.as_rgba_f32();
+            let mut color = Color32::from_rgba_premultiplied(
+                (col[0] * 256.0) as u8,
+                (col[1] * 256.0) as u8,
+                (col[2] * 256.0) as u8,
+                (col[3] * 256.0) as u8,
+            );
+            let stroke_col = outline_mode.color.as_rgba_f32();
+            let mut color2 = Color32::from_rgba_premultiplied(
+                (stroke_col[0] * 256.0) as u8,
+                (stroke_col[1] * 256.0) as u8,
+                (stroke_col[2] * 256.0) as u8,
+                (stroke_col[3] * 256.0) as u8,
+            );
+            let mut num = outline_mode.options.line_width;
+            ui.color_edit_button_srgba(&mut color);
+            ui.add(egui::DragValue::new(&mut num));
+            ui.color_edit_button_srgba(&mut color2);
+            *draw_mode = DrawMode::Outlined {
+                fill_mode: FillMode {
+                    options: fill_mode.options,
+                    color: Color::rgba_u8(color.r(), color.g(), color.b(), color.a()),
+                },
+                outline_mode: StrokeMode {
+                    options: outline_mode.options.with_line_width(num),
+                    color: Color::rgba_u8(color2.r(), color2.g(), color2.b(), color2.a()),
+                },
+            };
+        }
+    }
+}
+
+/// Caps, joins and miter limit, the width and color are edited with the stroke.
+fn stroke_options_widgets(ui: &mut egui::Ui, options: &mut StrokeOptions) {
+    ui.horizontal(|ui| {
+        ui.label("Caps");
+        for (label, cap) in [
+            ("Butt", LineCap::Butt),
+            ("Square", LineCap::Square),
+            ("Round", LineCap::Round),
+        ] {
+            if ui
+                .selectable_label(options.start_cap == cap, label)
+                .clicked()
+            {
+                options.start_cap = cap;
+                options.end_cap = cap;
+            }
+        }
+    });
+    ui.horizontal(|ui| {
+        ui.label("Joins");
+        for (label, join) in [
+            ("Miter", LineJoin::Miter),
+            ("Round", LineJoin::Round),
+            ("Bevel", LineJoin::Bevel),
+        ] {
+            if ui
+                .selectable_label(options.line_join == join, label)
+                .clicked()
+            {
+                options.line_join = join;
+            }
+        }
+    });
+    if options.line_join == LineJoin::Miter {
+        ui.horizontal(|ui| {
+            ui.label("Miter limit");
+            // Lyon doesn't accept limits below 1.
+            ui.add(
+                egui::DragValue::new(&mut options.miter_limit)
+                    .clamp_range(1.0..=100.0)
+                    .speed(0.1),
+            );
+        });
+    }
+}
+
+/// Dash and gap lengths, along with the offset the pattern starts at.
+fn dash_widgets(ui: &mut egui::Ui, dashes: &mut Option<Dashes>) {
+    let mut dashed = dashes.is_some();
+    if ui.checkbox(&mut dashed, "Dashed").changed() {
+        *dashes = dashed.then(Dashes::default);
+    }
+    let dashes = match dashes {
+        Some(dashes) => dashes,
+        None => return,
+    };
+    let mut removed = None;
+    let removable = dashes.lengths.len() > 1;
+    ui.horizontal_wrapped(|ui| {
+        for (i, length) in dashes.lengths.iter_mut().enumerate() {
+            ui.add(
+                egui::DragValue::new(length)
+                    .clamp_range(Dashes::MIN_LENGTH..=f32::MAX)
+                    .prefix(if i % 2 == 0 { "dash " } else { "gap " }),
+            );
+            if removable && ui.small_button("🗑").clicked() {
+                removed = Some(i);
+            }
+        }
+        if ui.small_button("+").clicked() {
+            let last = dashes.lengths.last().copied().unwrap_or(5.0);
+            dashes.lengths.push(last);
+        }
+    });
+    if let Some(i) = removed {
+        dashes.lengths.remove(i);
+    }
+    ui.horizontal(|ui| {
+        ui.label("Offset");
+        ui.add(egui::DragValue::new(&mut dashes.offset));
+    });
+}
+
+/// Markers at the start and end of an open path.
+fn marker_widgets(ui: &mut egui::Ui, markers: &mut Markers) {
+    for (label, marker) in [("Start", &mut markers.start), ("End", &mut markers.end)] {
+        ui.horizontal(|ui| {
+            ui.label(label);
+            for (value, name) in Marker::ALL {
+                ui.selectable_value(marker, value, name);
+            }
+        });
+    }
+}
+
+fn fill_color(draw_mode: &DrawMode) -> Color {
+    match draw_mode {
+        DrawMode::Fill(fill_mode) | DrawMode::Outlined { fill_mode, .. } => fill_mode.color,
+        DrawMode::Stroke(stroke_mode) => stroke_mode.color,
+    }
+}
+
+fn color_button(ui: &mut egui::Ui, color: &mut Color) {
+    let col = color.as_rgba_f32();
+    let mut color32 = Color32::from_rgba_premultiplied(
+        (col[0] * 256.0) as u8,
+        (col[1] * 256.0) as u8,
+        (col[2] * 256.0) as u8,
+        (col[3] * 256.0) as u8,
+    );
+    if ui.color_edit_button_srgba(&mut color32).changed() {
+        *color = Color::rgba_u8(color32.r(), color32.g(), color32.b(), color32.a());
+    }
+}
+
+/// Gradient kind and stops. The handles of a new gradient are placed by the caller, per shape.
+fn gradient_widgets(ui: &mut egui::Ui, gradient: &mut Option<Gradient>, fill: Color) {
+    let kind = gradient.as_ref().map(|g| g.kind);
+    ui.horizontal(|ui| {
+        ui.label("Gradient");
+        for (label, new_kind) in [
+            ("None", None),
+            ("Linear", Some(GradientKind::Linear)),
+            ("Radial", Some(GradientKind::Radial)),
+        ] {
+            if ui.selectable_label(kind == new_kind, label).clicked() && kind != new_kind {
+                *gradient = new_kind.map(|new_kind| match gradient {
+                    Some(g) => Gradient {
+                        kind: new_kind,
+                        ..g.clone()
+                    },
+                    None => Gradient::new(new_kind, fill),
+                });
+            }
+        }
+    });
+    let gradient = match gradient {
+        Some(gradient) => gradient,
+        None => return,
+    };
+    let mut removed = None;
+    let mut edited_offset = false;
+    let removable = gradient.stops.len() > 2;
+    for (i, stop) in gradient.stops.iter_mut().enumerate() {
+        ui.horizontal(|ui| {
+            color_button(ui, &mut stop.color);
+            let offset = ui.add(
+                egui::DragValue::new(&mut stop.offset)
+                    .clamp_range(0.0..=1.0)
+                    .speed(0.01),
+            );
+            edited_offset |= offset.drag_released() || offset.lost_focus();
+            if removable && ui.button("🗑").clicked() {
+                removed = Some(i);
+            }
+        });
+    }
+    if let Some(i) = removed {
+        gradient.stops.remove(i);
+    }
+    if gradient.stops.len() < MAX_STOPS && ui.button("Add stop").clicked() {
+        let last = gradient.stops[gradient.stops.len() - 1];
+        let previous = gradient.stops[gradient.stops.len() - 2];
+        gradient.stops.insert(
+            gradient.stops.len() - 1,
+            GradientStop {
+                offset: (previous.offset + last.offset) / 2.0,
+                color: last.color,
+            },
+        );
+    }
+    // Sorting while an offset is edited would move its row away from under the pointer.
+    if edited_offset {
+        gradient.sort_stops();
+    }
+}
+
+fn edit_properties(
+    mut egui_context: ResMut<EguiContext>,
+    mut mouse: ResMut<MouseMovement>,
+    mut per_corner: Local<bool>,
+    selection: Query<(&Selection, Entity)>,
+    mut query: Query<(&mut PrimitiveShape, &mut Path)>,
+    mut history: EventWriter<HistoryEvent>,
+) {
+    let selected: Vec<Entity> = selection
+        .iter()
+        .filter_map(|(s, e)| if s.selected() { Some(e) } else { None })
+        .collect();
+    if selected.len() != 1 {
+        return;
+    }
+    if let Ok((mut current, mut path)) = query.get_mut(selected[0]) {
+        let mut primitive = current.clone();
+        match primitive.shape {
+            PrimitiveType::RoundedRectangle => {
+                if primitive
+                    .corner_radii
+                    .iter()
+                    .any(|r| *r != primitive.corner_radii[0])
+                {
+                    *per_corner = true;
+                }
+                egui::Window::new("Properties").show(egui_context.ctx_mut(), |ui| {
+                    corner_radius_widgets(ui, &mut primitive.corner_radii, &mut per_corner);
+                });
+            }
+            PrimitiveType::Polygon { .. } | PrimitiveType::Star { .. } => {
+                egui::Window::new("Properties").show(egui_context.ctx_mut(), |ui| {
+                    polygon_widgets(ui, &mut primitive.shape);
+                });
+            }
+            _ => return,
+        }
+        if primitive != *current {
+            history.send(HistoryEvent(EditAction::Reshaped {
+                entity: selected[0],
+                before: GeometryRecord::Primitive(current.clone()),
+                after: GeometryRecord::Primitive(primitive.clone()),
+            }));
+            *path = ShapePath::build_as(&primitive);
+            *current = primitive;
         }
         mouse.over_ui = egui_context.ctx_mut().wants_pointer_input();
     }
 }
+
+fn edit_connector(
+    mut egui_context: ResMut<EguiContext>,
+    mut mouse: ResMut<MouseMovement>,
+    selection: Query<(&Selection, Entity)>,
+    mut query: Query<&mut Connector>,
+    mut history: EventWriter<HistoryEvent>,
+) {
+    let selected: Vec<Entity> = selection
+        .iter()
+        .filter_map(|(s, e)| if s.selected() { Some(e) } else { None })
+        .collect();
+    if selected.len() != 1 {
+        return;
+    }
+    if let Ok(mut current) = query.get_mut(selected[0]) {
+        let mut connector = *current;
+        egui::Window::new("Connector").show(egui_context.ctx_mut(), |ui| {
+            routing_widgets(ui, &mut connector.routing);
+            egui::Grid::new("anchors").show(ui, |ui| {
+                for (label, anchor) in
+                    [("Start", &mut connector.start), ("End", &mut connector.end)]
+                {
+                    ui.label(label);
+                    egui::ComboBox::from_id_source(label)
+                        .selected_text(
+                            AnchorPoint::ALL
+                                .iter()
+                                .find(|(p, _)| *p == anchor.point)
+                                .map_or("", |(_, name)| *name),
+                        )
+                        .show_ui(ui, |ui| {
+                            for (point, name) in AnchorPoint::ALL {
+                                ui.selectable_value(&mut anchor.point, point, name);
+                            }
+                        });
+                    ui.end_row();
+                }
+            });
+        });
+        if connector != *current {
+            history.send(HistoryEvent(EditAction::Rerouted {
+                entity: selected[0],
+                before: *current,
+                after: connector,
+            }));
+            *current = connector;
+        }
+        mouse.over_ui = egui_context.ctx_mut().wants_pointer_input();
+    }
+}
+
+fn routing_widgets(ui: &mut egui::Ui, routing: &mut Routing) {
+    ui.horizontal(|ui| {
+        ui.label("Routing");
+        ui.selectable_value(routing, Routing::Straight, "Straight");
+        ui.selectable_value(routing, Routing::Elbow, "Elbow");
+    });
+}
+
+fn corner_radius_widgets(ui: &mut egui::Ui, corner_radii: &mut [f32; 4], per_corner: &mut bool) {
+    ui.checkbox(per_corner, "Per corner radius");
+    if *per_corner {
+        egui::Grid::new("corner_radii").show(ui, |ui| {
+            for (label, radius) in ["Top left", "Top right", "Bottom right", "Bottom left"]
+                .iter()
+                .zip(corner_radii.iter_mut())
+            {
+                ui.label(*label);
+                ui.add(egui::DragValue::new(radius).clamp_range(0.0..=f32::MAX));
+                ui.end_row();
+            }
+        });
+    } else {
+        let mut radius = corner_radii[0];
+        ui.horizontal(|ui| {
+            ui.label("Corner radius");
+            ui.add(egui::DragValue::new(&mut radius).clamp_range(0.0..=f32::MAX));
+        });
+        if radius != corner_radii[0] {
+            *corner_radii = [radius; 4];
+        }
+    }
+}
+
+/// Side count of a polygon, or point count and inner radius of a star.
+fn polygon_widgets(ui: &mut egui::Ui, shape: &mut PrimitiveType) {
+    match shape {
+        PrimitiveType::Polygon { sides } => {
+            ui.horizontal(|ui| {
+                ui.label("Sides");
+                ui.add(egui::DragValue::new(sides).clamp_range(3..=100));
+            });
+        }
+        PrimitiveType::Star {
+            points,
+            inner_ratio,
+        } => {
+            ui.horizontal(|ui| {
+                ui.label("Points");
+                ui.add(egui::DragValue::new(points).clamp_range(3..=100));
+            });
+            ui.horizontal(|ui| {
+                ui.label("Inner radius");
+                ui.add(
+                    egui::DragValue::new(inner_ratio)
+                        .speed(0.01)
+                        .clamp_range(0.0..=1.0),
+                );
+            });
+        }
+        _ => {}
+    }
+}
+
+fn grid_settings(
+    mut egui_context: ResMut<EguiContext>,
+    mut grid: ResMut<Grid>,
+    mut object_snap: ResMut<ObjectSnap>,
+    mut mouse: ResMut<MouseMovement>,
+) {
+    // Edited on a copy so that the grid is only rebuilt when a setting actually changes.
+    let (mut visible, mut snap, mut spacing, mut subdivisions) =
+        (grid.visible, grid.snap, grid.spacing, grid.subdivisions);
+    egui::Window::new("Grid").show(egui_context.ctx_mut(), |ui| {
+        ui.checkbox(&mut visible, "Show grid");
+        ui.checkbox(&mut snap, "Snap to grid");
+        ui.checkbox(&mut object_snap.enabled, "Snap to objects");
+        ui.horizontal(|ui| {
+            ui.label("Spacing");
+            ui.add(egui::DragValue::new(&mut spacing).clamp_range(1.0..=10000.0));
+        });
+        ui.horizontal(|ui| {
+            ui.label("Subdivisions");
+            ui.add(egui::DragValue::new(&mut subdivisions).clamp_range(1..=20));
+        });
+    });
+    if (visible, snap, spacing, subdivisions)
+        != (grid.visible, grid.snap, grid.spacing, grid.subdivisions)
+    {
+        *grid = Grid {
+            visible,
+            snap,
+            spacing,
+            subdivisions,
+        };
+    }
+    mouse.over_ui = egui_context.ctx_mut().wants_pointer_input();
+}
+
+fn align_panel(mut egui_context: ResMut<EguiContext>, mut events: EventWriter<AlignEvent>) {
+    egui::Window::new("Align").show(egui_context.ctx_mut(), |ui| {
+        ui.label("Align");
+        ui.horizontal_wrapped(|ui| {
+            for (alignment, label) in Alignment::ALIGN {
+                if ui.button(label).clicked() {
+                    events.send(AlignEvent(alignment));
+                }
+            }
+        });
+        ui.label("Distribute");
+        ui.horizontal_wrapped(|ui| {
+            for (alignment, label) in Alignment::DISTRIBUTE {
+                if ui.button(label).clicked() {
+                    events.send(AlignEvent(alignment));
+                }
+            }
+        });
+    });
+}
+
+fn path_panel(mut egui_context: ResMut<EguiContext>, mut events: EventWriter<PathOpEvent>) {
+    egui::Window::new("Path").show(egui_context.ctx_mut(), |ui| {
+        ui.horizontal_wrapped(|ui| {
+            for (operation, label) in PathOperation::ALL {
+                if ui.button(label).clicked() {
+                    events.send(PathOpEvent(operation));
+                }
+            }
+        });
+    });
+}
+
+fn edit_transform(
+    mut egui_context: ResMut<EguiContext>,
+    mut mouse: ResMut<MouseMovement>,
+    mut lock_aspect: Local<bool>,
+    selection: Query<(&Selection, Entity), (With<ShapeBase>, Without<Parent>)>,
+    meshes: Res<Assets<Mesh>>,
+    mut shapes: ParamSet<(
+        BoundsQuery,
+        Query<&mut Transform, (With<ShapeBase>, Without<Locked>)>,
+    )>,
+    mut history: EventWriter<HistoryEvent>,
+) {
+    let selected: Vec<Entity> = selection
+        .iter()
+        .filter_map(|(s, e)| if s.selected() { Some(e) } else { None })
+        .collect();
+    if selected.len() != 1 {
+        return;
+    }
+    let entity = selected[0];
+    // Same size as the one the scale pickers work with: the local AABB times the scale.
+    let aabb_size = match shape_bounds(entity, &meshes, &shapes.p0()) {
+        Some(aabb) => aabb.half_extents.truncate() * 2.0,
+        None => return,
+    };
+    let mut transforms = shapes.p1();
+    let mut transform = match transforms.get_mut(entity) {
+        Ok(transform) => transform,
+        Err(_) => return,
+    };
+    let (axis, angle) = transform.rotation.to_axis_angle();
+    let old_rotation = (axis.z * angle).to_degrees();
+    let old_position = transform.translation.truncate();
+    let old_size = aabb_size * transform.scale.truncate();
+    let (mut position, mut rotation) = (old_position, old_rotation);
+    // Flipped shapes have a negative size, only its magnitude is edited.
+    let mut extents = old_size.abs();
+    egui::Window::new("Transform").show(egui_context.ctx_mut(), |ui| {
+        egui::Grid::new("transform").show(ui, |ui| {
+            ui.label("X");
+            ui.add(egui::DragValue::new(&mut position.x));
+            ui.label("Y");
+            ui.add(egui::DragValue::new(&mut position.y));
+            ui.end_row();
+            ui.label("Width");
+            ui.add_enabled(
+                aabb_size.x > 0.0,
+                egui::DragValue::new(&mut extents.x).clamp_range(0.01..=f32::MAX),
+            );
+            ui.label("Height");
+            ui.add_enabled(
+                aabb_size.y > 0.0,
+                egui::DragValue::new(&mut extents.y).clamp_range(0.01..=f32::MAX),
+            );
+            ui.end_row();
+            ui.label("Rotation");
+            ui.add(egui::DragValue::new(&mut rotation).suffix("°"));
+            ui.end_row();
+        });
+        ui.checkbox(&mut lock_aspect, "Lock aspect ratio");
+    });
+    let mut size = Vec2::new(
+        extents.x.copysign(old_size.x),
+        extents.y.copysign(old_size.y),
+    );
+    if *lock_aspect && old_size.x != 0.0 && old_size.y != 0.0 {
+        if size.x != old_size.x {
+            size.y = old_size.y * size.x / old_size.x;
+        } else if size.y != old_size.y {
+            size.x = old_size.x * size.y / old_size.y;
+        }
+    }
+    if (position, size, rotation) != (old_position, old_size, old_rotation) {
+        let before = *transform;
+        transform.translation = position.extend(transform.translation.z);
+        transform.rotation = Quat::from_rotation_z(rotation.to_radians());
+        let scale = Vec2::select(aabb_size.cmpgt(Vec2::ZERO), size / aabb_size, Vec2::ONE);
+        transform.scale = scale.extend(transform.scale.z);
+        history.send(HistoryEvent(EditAction::Transformed {
+            entity,
+            before,
+            after: *transform,
+        }));
+    }
+    mouse.over_ui = egui_context.ctx_mut().wants_pointer_input();
+}